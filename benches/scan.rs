@@ -0,0 +1,84 @@
+//! Compares a linear scan that pushes through `push_descriptor` against one that counts bits a word at a time.
+//!
+//! `push_descriptor` picks a SIMD popcount kernel at runtime when the CPU supports one, and
+//! `push_descriptor_bounded` stops counting once a feature can't beat the worst candidate.
+
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use hamming_heap::{FixedBinaryDescriptor, FixedHammingHeap};
+
+/// Produces `len` random descriptors from a fixed seed so each run sees the same input.
+fn descriptors<const N: usize>(len: usize) -> Vec<[u8; N]> {
+    let mut state = 0x2545_f491_4f6c_dd1du64;
+    (0..len)
+        .map(|_| {
+            let mut descriptor = [0u8; N];
+            for byte in descriptor.iter_mut() {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                *byte = state as u8;
+            }
+            descriptor
+        })
+        .collect()
+}
+
+/// Counts the differing bits a word at a time, like a hand-written scan would.
+fn words_hamming(a: &[u8], b: &[u8]) -> u32 {
+    a.chunks_exact(8)
+        .zip(b.chunks_exact(8))
+        .map(|(a, b)| {
+            let mut a_word = [0u8; 8];
+            let mut b_word = [0u8; 8];
+            a_word.copy_from_slice(a);
+            b_word.copy_from_slice(b);
+            (u64::from_ne_bytes(a_word) ^ u64::from_ne_bytes(b_word)).count_ones()
+        })
+        .sum()
+}
+
+fn bench_width<const N: usize>(c: &mut Criterion) {
+    let features = descriptors::<N>(10_000);
+    let query = features[0];
+    let mut group = c.benchmark_group("scan");
+    let mut candidates: FixedHammingHeap<usize> = FixedHammingHeap::for_descriptor::<[u8; N]>();
+    candidates.set_capacity(10);
+    let bits = <[u8; N] as FixedBinaryDescriptor>::BITS;
+    group.bench_function(BenchmarkId::new("push_descriptor", bits), |b| {
+        b.iter(|| {
+            candidates.clear();
+            for (ix, feature) in features.iter().enumerate() {
+                candidates.push_descriptor(black_box(&query), feature, ix);
+            }
+            black_box(candidates.len())
+        })
+    });
+    group.bench_function(BenchmarkId::new("push_descriptor_bounded", bits), |b| {
+        b.iter(|| {
+            candidates.clear();
+            for (ix, feature) in features.iter().enumerate() {
+                candidates.push_descriptor_bounded(black_box(&query), feature, ix);
+            }
+            black_box(candidates.len())
+        })
+    });
+    group.bench_function(BenchmarkId::new("words", bits), |b| {
+        b.iter(|| {
+            candidates.clear();
+            for (ix, feature) in features.iter().enumerate() {
+                candidates.push(words_hamming(black_box(&query), feature), ix);
+            }
+            black_box(candidates.len())
+        })
+    });
+    group.finish();
+}
+
+fn scan(c: &mut Criterion) {
+    bench_width::<32>(c);
+    bench_width::<64>(c);
+    bench_width::<128>(c);
+}
+
+criterion_group!(benches, scan);
+criterion_main!(benches);
@@ -0,0 +1,169 @@
+//! Compares the occupancy bitset against walking every distance bucket when the heaps are sparse.
+//!
+//! `LinearHammingHeap` and `LinearFixedHammingHeap` reproduce the bucket walk the heaps used before
+//! they tracked occupied distances, so the two can be compared on the same inputs.
+
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use hamming_heap::{FixedHammingHeap, HammingHeap};
+
+/// Produces `len` distances in `0..distances` from a fixed seed so each run sees the same input.
+fn sparse_distances(len: usize, distances: usize) -> Vec<u32> {
+    let mut state = 0x2545_f491_4f6c_dd1du64;
+    (0..len)
+        .map(|_| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            (state % distances as u64) as u32
+        })
+        .collect()
+}
+
+struct LinearHammingHeap {
+    distances: Vec<Vec<u32>>,
+    best: u32,
+}
+
+impl LinearHammingHeap {
+    fn new_distances(distances: usize) -> Self {
+        Self {
+            distances: (0..distances).map(|_| vec![]).collect(),
+            best: 0,
+        }
+    }
+
+    fn push(&mut self, distance: u32, node: u32) {
+        if distance < self.best {
+            self.best = distance;
+        }
+        self.distances[distance as usize].push(node);
+    }
+
+    fn pop(&mut self) -> Option<(u32, u32)> {
+        loop {
+            if let Some(node) = self.distances[self.best as usize].pop() {
+                return Some((self.best, node));
+            } else if self.best == self.distances.len() as u32 - 1 {
+                return None;
+            } else {
+                self.best += 1;
+            }
+        }
+    }
+}
+
+struct LinearFixedHammingHeap {
+    cap: usize,
+    size: usize,
+    worst: u32,
+    distances: Vec<Vec<u32>>,
+}
+
+impl LinearFixedHammingHeap {
+    fn new_distances(distances: usize, cap: usize) -> Self {
+        Self {
+            cap,
+            size: 0,
+            worst: distances as u32 - 1,
+            distances: (0..distances).map(|_| vec![]).collect(),
+        }
+    }
+
+    fn push(&mut self, distance: u32, item: u32) -> bool {
+        if self.size != self.cap {
+            self.distances[distance as usize].push(item);
+            self.size += 1;
+            if self.size == self.cap {
+                self.update_worst();
+            }
+            true
+        } else if distance < self.worst {
+            self.distances[distance as usize].push(item);
+            self.distances[self.worst as usize].pop();
+            self.update_worst();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn clear(&mut self) {
+        for v in &mut self.distances {
+            v.clear();
+        }
+        self.size = 0;
+        self.worst = self.distances.len() as u32 - 1;
+    }
+
+    fn update_worst(&mut self) {
+        self.worst = self.distances[0..=self.worst as usize]
+            .iter()
+            .rev()
+            .position(|v| !v.is_empty())
+            .map(|n| self.worst - n as u32)
+            .unwrap_or(self.distances.len() as u32 - 1);
+    }
+}
+
+fn pop_all(c: &mut Criterion) {
+    let mut group = c.benchmark_group("pop_all");
+    for &distances in &[129, 257, 513] {
+        let input = sparse_distances(32, distances);
+        group.bench_with_input(BenchmarkId::new("bitset", distances), &input, |b, input| {
+            let mut heap = HammingHeap::new_distances(distances);
+            b.iter(|| {
+                for (ix, &distance) in input.iter().enumerate() {
+                    heap.push(distance, ix as u32);
+                }
+                while let Some(item) = heap.pop() {
+                    black_box(item);
+                }
+            })
+        });
+        group.bench_with_input(BenchmarkId::new("linear", distances), &input, |b, input| {
+            let mut heap = LinearHammingHeap::new_distances(distances);
+            b.iter(|| {
+                heap.best = 0;
+                for (ix, &distance) in input.iter().enumerate() {
+                    heap.push(distance, ix as u32);
+                }
+                while let Some(item) = heap.pop() {
+                    black_box(item);
+                }
+            })
+        });
+    }
+    group.finish();
+}
+
+fn fixed_push(c: &mut Criterion) {
+    let mut group = c.benchmark_group("fixed_push");
+    for &distances in &[129, 257, 513] {
+        let input = sparse_distances(4096, distances);
+        group.bench_with_input(BenchmarkId::new("bitset", distances), &input, |b, input| {
+            let mut heap = FixedHammingHeap::new_distances(distances);
+            heap.set_capacity(16);
+            b.iter(|| {
+                heap.clear();
+                for (ix, &distance) in input.iter().enumerate() {
+                    heap.push(distance, ix as u32);
+                }
+                black_box(heap.worst())
+            })
+        });
+        group.bench_with_input(BenchmarkId::new("linear", distances), &input, |b, input| {
+            let mut heap = LinearFixedHammingHeap::new_distances(distances, 16);
+            b.iter(|| {
+                heap.clear();
+                for (ix, &distance) in input.iter().enumerate() {
+                    heap.push(distance, ix as u32);
+                }
+                black_box(heap.worst)
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, pop_all, fixed_push);
+criterion_main!(benches);
@@ -0,0 +1,470 @@
+use core::fmt;
+use core::marker::PhantomData;
+use core::mem::MaybeUninit;
+use core::ptr;
+
+/// The storage backend that holds the items for every distance.
+///
+/// Each distance owns one bucket, and each bucket is a contiguous slice of items.
+/// The heaps add items at the end of a bucket and mostly remove them from the end as well, so a backend mainly
+/// needs to behave like one stack per distance. Items are only inserted into or removed from the middle of a bucket
+/// when a [`TieBreak`](crate::TieBreak) policy other than `Lifo` is used.
+///
+/// Two backends are provided:
+///
+/// * [`VecBuckets`] keeps one `Vec` per distance. This is the default.
+/// * [`FlatBuckets`] keeps every item in one arena, which avoids an allocation per distance and
+///   keeps the items of neighboring distances close together in memory.
+///
+/// The heaps take the backend as a type parameter, so switching is a change to the type only:
+///
+/// ```
+/// use hamming_heap::{FlatBuckets, HammingHeap};
+/// let mut candidates: HammingHeap<u32, u32, FlatBuckets<u32>> = HammingHeap::default();
+/// candidates.set_distances(129);
+/// candidates.push(3, 0);
+/// assert_eq!(candidates.pop(), Some((3, 0)));
+/// ```
+pub trait Buckets<T>: Default {
+    /// Iterator over every bucket as a mutable slice, in order of distance.
+    type BucketsMut<'a>: DoubleEndedIterator<Item = &'a mut [T]> + ExactSizeIterator
+    where
+        Self: 'a,
+        T: 'a;
+
+    /// Drops all items and sets the number of buckets to `buckets`.
+    fn reset(&mut self, buckets: usize);
+
+    /// Adds empty buckets to the end until there are `buckets` buckets, keeping all items.
+    fn grow(&mut self, buckets: usize);
+
+    /// Gets the number of buckets.
+    fn num_buckets(&self) -> usize;
+
+    /// Gets the items in `bucket`.
+    fn bucket(&self, bucket: usize) -> &[T];
+
+    /// Gets the items in `bucket` mutably.
+    fn bucket_mut(&mut self, bucket: usize) -> &mut [T];
+
+    /// Iterate over every bucket mutably in order of distance.
+    fn buckets_mut(&mut self) -> Self::BucketsMut<'_>;
+
+    /// Adds an item to the end of `bucket`.
+    fn push(&mut self, bucket: usize, item: T);
+
+    /// Removes the item at the end of `bucket`.
+    fn pop(&mut self, bucket: usize) -> Option<T>;
+
+    /// Drops items from the end of `bucket` until it contains `len` items.
+    fn truncate(&mut self, bucket: usize, len: usize);
+
+    /// Inserts an item at `index` in `bucket`, shifting the items after it up to preserve their order.
+    ///
+    /// Panics if `index` is greater than the length of the bucket.
+    fn insert(&mut self, bucket: usize, index: usize, item: T) {
+        assert!(
+            index <= self.bucket(bucket).len(),
+            "insertion index out of bounds"
+        );
+        self.push(bucket, item);
+        self.bucket_mut(bucket)[index..].rotate_right(1);
+    }
+
+    /// Removes the item at `index` in `bucket`, shifting the items after it down to preserve their order.
+    ///
+    /// Panics if `index` is out of bounds.
+    fn remove(&mut self, bucket: usize, index: usize) -> T {
+        self.bucket_mut(bucket)[index..].rotate_left(1);
+        self.pop(bucket).expect("bucket was empty")
+    }
+
+    /// Drops all items in `bucket`.
+    fn clear(&mut self, bucket: usize) {
+        self.truncate(bucket, 0);
+    }
+
+    /// Drops the items in `bucket` for which `keep` returns false, preserving the order of the rest.
+    fn retain<F>(&mut self, bucket: usize, mut keep: F)
+    where
+        F: FnMut(&T) -> bool,
+    {
+        let items = self.bucket_mut(bucket);
+        let mut kept = 0;
+        for ix in 0..items.len() {
+            if keep(&items[ix]) {
+                items.swap(kept, ix);
+                kept += 1;
+            }
+        }
+        self.truncate(bucket, kept);
+    }
+}
+
+/// Stores each distance in its own `Vec`.
+#[derive(Clone, Debug)]
+pub struct VecBuckets<T> {
+    buckets: Vec<Vec<T>>,
+}
+
+impl<T> Default for VecBuckets<T> {
+    fn default() -> Self {
+        Self { buckets: vec![] }
+    }
+}
+
+impl<T> Buckets<T> for VecBuckets<T> {
+    type BucketsMut<'a>
+        = core::iter::Map<core::slice::IterMut<'a, Vec<T>>, fn(&'a mut Vec<T>) -> &'a mut [T]>
+    where
+        T: 'a;
+
+    fn reset(&mut self, buckets: usize) {
+        self.buckets.clear();
+        self.buckets.resize_with(buckets, Vec::new);
+    }
+
+    fn grow(&mut self, buckets: usize) {
+        if buckets > self.buckets.len() {
+            self.buckets.resize_with(buckets, Vec::new);
+        }
+    }
+
+    #[inline]
+    fn num_buckets(&self) -> usize {
+        self.buckets.len()
+    }
+
+    #[inline]
+    fn bucket(&self, bucket: usize) -> &[T] {
+        &self.buckets[bucket]
+    }
+
+    #[inline]
+    fn bucket_mut(&mut self, bucket: usize) -> &mut [T] {
+        &mut self.buckets[bucket]
+    }
+
+    fn buckets_mut(&mut self) -> Self::BucketsMut<'_> {
+        self.buckets.iter_mut().map(Vec::as_mut_slice)
+    }
+
+    #[inline]
+    fn push(&mut self, bucket: usize, item: T) {
+        self.buckets[bucket].push(item);
+    }
+
+    #[inline]
+    fn pop(&mut self, bucket: usize) -> Option<T> {
+        self.buckets[bucket].pop()
+    }
+
+    #[inline]
+    fn truncate(&mut self, bucket: usize, len: usize) {
+        self.buckets[bucket].truncate(len);
+    }
+
+    #[inline]
+    fn insert(&mut self, bucket: usize, index: usize, item: T) {
+        self.buckets[bucket].insert(index, item);
+    }
+
+    #[inline]
+    fn remove(&mut self, bucket: usize, index: usize) -> T {
+        self.buckets[bucket].remove(index)
+    }
+
+    fn retain<F>(&mut self, bucket: usize, keep: F)
+    where
+        F: FnMut(&T) -> bool,
+    {
+        self.buckets[bucket].retain(keep);
+    }
+}
+
+/// The region of the arena owned by one bucket.
+#[derive(Copy, Clone, Debug, Default)]
+struct Segment {
+    start: usize,
+    len: usize,
+    cap: usize,
+}
+
+/// Stores every distance in one contiguous arena.
+///
+/// Each bucket owns a segment of the arena. When a segment fills up, it is moved to the end of the arena with
+/// double the room, and the space it leaves behind is reclaimed once it makes up more than half of the arena.
+/// Resetting only drops the items and the segment table, so the arena allocation is kept between searches and
+/// `set_distances` doesn't allocate one `Vec` per distance.
+pub struct FlatBuckets<T> {
+    arena: Vec<MaybeUninit<T>>,
+    segments: Vec<Segment>,
+    /// Number of slots in the arena that no longer belong to any segment.
+    abandoned: usize,
+}
+
+impl<T> FlatBuckets<T> {
+    /// Moves `bucket` to the end of the arena with room for at least one more item.
+    fn grow_segment(&mut self, bucket: usize) {
+        let Segment { start, len, cap } = self.segments[bucket];
+        let new_cap = core::cmp::max(4, cap * 2);
+        if start + cap == self.arena.len() {
+            // The segment is already at the end of the arena, so it can grow in place.
+            self.arena.resize_with(start + new_cap, MaybeUninit::uninit);
+            self.segments[bucket].cap = new_cap;
+            return;
+        }
+        let new_start = self.arena.len();
+        self.arena
+            .resize_with(new_start + new_cap, MaybeUninit::uninit);
+        // SAFETY: The new segment lies past the end of every existing segment, so the regions don't overlap.
+        // The items are moved, and the old slots are treated as uninitialized from here on.
+        unsafe {
+            let base = self.arena.as_mut_ptr();
+            ptr::copy_nonoverlapping(base.add(start), base.add(new_start), len);
+        }
+        self.segments[bucket] = Segment {
+            start: new_start,
+            len,
+            cap: new_cap,
+        };
+        self.abandoned += cap;
+        if self.abandoned * 2 > self.arena.len() {
+            self.compact();
+        }
+    }
+
+    /// Rebuilds the arena with the segments packed together in order of distance.
+    fn compact(&mut self) {
+        let total = self.segments.iter().map(|segment| segment.cap).sum();
+        let mut arena: Vec<MaybeUninit<T>> = Vec::with_capacity(total);
+        for segment in &mut self.segments {
+            let start = arena.len();
+            arena.resize_with(start + segment.cap, MaybeUninit::uninit);
+            // SAFETY: The items are moved out of the old arena, which is only dropped as `MaybeUninit`.
+            unsafe {
+                ptr::copy_nonoverlapping(
+                    self.arena.as_ptr().add(segment.start),
+                    arena.as_mut_ptr().add(start),
+                    segment.len,
+                );
+            }
+            segment.start = start;
+        }
+        self.arena = arena;
+        self.abandoned = 0;
+    }
+}
+
+impl<T> Default for FlatBuckets<T> {
+    fn default() -> Self {
+        Self {
+            arena: vec![],
+            segments: vec![],
+            abandoned: 0,
+        }
+    }
+}
+
+impl<T> Drop for FlatBuckets<T> {
+    fn drop(&mut self) {
+        for bucket in 0..self.segments.len() {
+            self.clear(bucket);
+        }
+    }
+}
+
+impl<T> Clone for FlatBuckets<T>
+where
+    T: Clone,
+{
+    fn clone(&self) -> Self {
+        let mut buckets = Self::default();
+        buckets.reset(self.segments.len());
+        for bucket in 0..self.segments.len() {
+            for item in self.bucket(bucket) {
+                buckets.push(bucket, item.clone());
+            }
+        }
+        buckets
+    }
+}
+
+impl<T> fmt::Debug for FlatBuckets<T>
+where
+    T: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list()
+            .entries((0..self.segments.len()).map(|bucket| self.bucket(bucket)))
+            .finish()
+    }
+}
+
+/// Iterator over the buckets of a [`FlatBuckets`] as mutable slices.
+pub struct FlatBucketsMut<'a, T> {
+    base: *mut T,
+    segments: core::slice::Iter<'a, Segment>,
+    _marker: PhantomData<&'a mut T>,
+}
+
+impl<'a, T> FlatBucketsMut<'a, T> {
+    fn slice(&self, segment: &Segment) -> &'a mut [T] {
+        // SAFETY: Segments never overlap and every slot below `len` is initialized, so each yielded slice
+        // is a unique borrow of initialized items for the lifetime of the borrow of the arena.
+        unsafe { core::slice::from_raw_parts_mut(self.base.add(segment.start), segment.len) }
+    }
+}
+
+impl<'a, T> Iterator for FlatBucketsMut<'a, T> {
+    type Item = &'a mut [T];
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let segment = self.segments.next()?;
+        Some(self.slice(segment))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.segments.size_hint()
+    }
+}
+
+impl<T> DoubleEndedIterator for FlatBucketsMut<'_, T> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        let segment = self.segments.next_back()?;
+        Some(self.slice(segment))
+    }
+}
+
+impl<T> ExactSizeIterator for FlatBucketsMut<'_, T> {}
+
+impl<T> Buckets<T> for FlatBuckets<T> {
+    type BucketsMut<'a>
+        = FlatBucketsMut<'a, T>
+    where
+        T: 'a;
+
+    fn reset(&mut self, buckets: usize) {
+        for bucket in 0..self.segments.len() {
+            self.clear(bucket);
+        }
+        self.arena.clear();
+        self.segments.clear();
+        self.segments.resize(buckets, Segment::default());
+        self.abandoned = 0;
+    }
+
+    fn grow(&mut self, buckets: usize) {
+        if buckets > self.segments.len() {
+            self.segments.resize(buckets, Segment::default());
+        }
+    }
+
+    #[inline]
+    fn num_buckets(&self) -> usize {
+        self.segments.len()
+    }
+
+    #[inline]
+    fn bucket(&self, bucket: usize) -> &[T] {
+        let Segment { start, len, .. } = self.segments[bucket];
+        // SAFETY: The first `len` slots of a segment are always initialized.
+        unsafe { core::slice::from_raw_parts(self.arena.as_ptr().add(start) as *const T, len) }
+    }
+
+    #[inline]
+    fn bucket_mut(&mut self, bucket: usize) -> &mut [T] {
+        let Segment { start, len, .. } = self.segments[bucket];
+        // SAFETY: The first `len` slots of a segment are always initialized.
+        unsafe {
+            core::slice::from_raw_parts_mut(self.arena.as_mut_ptr().add(start) as *mut T, len)
+        }
+    }
+
+    fn buckets_mut(&mut self) -> Self::BucketsMut<'_> {
+        FlatBucketsMut {
+            base: self.arena.as_mut_ptr() as *mut T,
+            segments: self.segments.iter(),
+            _marker: PhantomData,
+        }
+    }
+
+    #[inline]
+    fn push(&mut self, bucket: usize, item: T) {
+        if self.segments[bucket].len == self.segments[bucket].cap {
+            self.grow_segment(bucket);
+        }
+        let segment = &mut self.segments[bucket];
+        self.arena[segment.start + segment.len] = MaybeUninit::new(item);
+        segment.len += 1;
+    }
+
+    #[inline]
+    fn pop(&mut self, bucket: usize) -> Option<T> {
+        let segment = &mut self.segments[bucket];
+        if segment.len == 0 {
+            return None;
+        }
+        segment.len -= 1;
+        // SAFETY: The slot was initialized and is now past the end of the segment, so it is read exactly once.
+        Some(unsafe { self.arena[segment.start + segment.len].as_ptr().read() })
+    }
+
+    fn truncate(&mut self, bucket: usize, len: usize) {
+        let segment = &mut self.segments[bucket];
+        if len >= segment.len {
+            return;
+        }
+        let old_len = segment.len;
+        // Shorten the segment first so that a panicking destructor can't cause a double drop.
+        segment.len = len;
+        // SAFETY: The slots in `len..old_len` are initialized and no longer reachable through the segment.
+        unsafe {
+            let tail = self.arena.as_mut_ptr().add(segment.start + len) as *mut T;
+            ptr::drop_in_place(ptr::slice_from_raw_parts_mut(tail, old_len - len));
+        }
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn test_flat_buckets() {
+    let mut buckets: FlatBuckets<String> = FlatBuckets::default();
+    let mut reference: VecBuckets<String> = VecBuckets::default();
+    buckets.reset(7);
+    reference.reset(7);
+    for ix in 0..200usize {
+        let bucket = ix * 5 % 7;
+        buckets.push(bucket, ix.to_string());
+        reference.push(bucket, ix.to_string());
+        if ix % 3 == 0 {
+            assert_eq!(buckets.pop(ix % 7), reference.pop(ix % 7));
+        }
+        if ix % 11 == 0 && reference.bucket(3).len() > 1 {
+            assert_eq!(buckets.remove(3, 1), reference.remove(3, 1));
+        }
+        if ix % 13 == 0 {
+            let index = reference.bucket(4).len() / 2;
+            buckets.insert(4, index, ix.to_string());
+            reference.insert(4, index, ix.to_string());
+        }
+        if ix % 50 == 0 {
+            buckets.truncate(2, 4);
+            reference.truncate(2, 4);
+        }
+    }
+    for bucket in 0..7 {
+        assert_eq!(buckets.bucket(bucket), reference.bucket(bucket));
+    }
+    let cloned = buckets.clone();
+    for (a, b) in buckets
+        .buckets_mut()
+        .rev()
+        .zip(reference.buckets_mut().rev())
+    {
+        assert_eq!(a, b);
+    }
+    for bucket in 0..7 {
+        assert_eq!(cloned.bucket(bucket), reference.bucket(bucket));
+    }
+}
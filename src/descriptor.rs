@@ -0,0 +1,238 @@
+use crate::distance::Distance;
+use crate::popcount;
+use crate::HammingHeapError;
+
+/// A binary feature descriptor that the heaps can compute Hamming distances for.
+///
+/// This is implemented for the unsigned integers, for `[u8; N]` and `[u64; N]`, and for `[u8]` and `[u64]` slices.
+/// The arrays and slices pick an AVX2 popcount kernel at runtime when the CPU supports one, or an AVX-512 kernel
+/// with the `avx512` feature, and fall back to counting a word at a time otherwise.
+/// Descriptors with a width known at compile time also implement [`FixedBinaryDescriptor`], which lets the heaps
+/// derive their number of distances from the type with `for_descriptor`.
+///
+/// ```
+/// use hamming_heap::BinaryDescriptor;
+/// let a = [0b1011u8, 0xff];
+/// let b = [0b0001u8, 0x0f];
+/// assert_eq!(a.hamming(&b), 6);
+/// assert_eq!(a[..].bits(), 16);
+/// ```
+pub trait BinaryDescriptor {
+    /// Gets the number of bits in this descriptor.
+    fn bits(&self) -> usize;
+
+    /// Gets the number of bits that differ between this descriptor and `other`.
+    ///
+    /// Panics if the descriptors are slices of different lengths.
+    fn hamming(&self, other: &Self) -> u32;
+
+    /// Gets the number of bits that differ between this descriptor and `other` if it is below `bound`.
+    ///
+    /// Arrays and slices count a 64-bit word at a time and abandon the computation as soon as the partial count
+    /// reaches `bound`, reporting how many words they skipped. Other descriptors compute the whole distance.
+    ///
+    /// ```
+    /// use hamming_heap::{BinaryDescriptor, BoundedDistance};
+    /// let a = [!0u64; 8];
+    /// let b = [0u64; 8];
+    /// assert_eq!(a.hamming_bounded(&b, 100), BoundedDistance::Abandoned { skipped_words: 6 });
+    /// assert_eq!(a.hamming_bounded(&b, 513), BoundedDistance::Within(512));
+    /// ```
+    ///
+    /// Panics if the descriptors are slices of different lengths.
+    fn hamming_bounded(&self, other: &Self, bound: u32) -> BoundedDistance {
+        let distance = self.hamming(other);
+        if distance < bound {
+            BoundedDistance::Within(distance)
+        } else {
+            BoundedDistance::Abandoned { skipped_words: 0 }
+        }
+    }
+}
+
+/// The distance between two descriptors, unless computing it was abandoned because it reached a bound.
+///
+/// This is returned by [`BinaryDescriptor::hamming_bounded`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum BoundedDistance {
+    /// The distance is below the bound.
+    Within(u32),
+    /// The distance reached the bound before every word was looked at.
+    Abandoned {
+        /// The number of 64-bit words that were never looked at.
+        skipped_words: usize,
+    },
+}
+
+/// A [`BinaryDescriptor`] whose number of bits is known at compile time.
+pub trait FixedBinaryDescriptor: BinaryDescriptor {
+    /// The number of bits in every descriptor of this type.
+    const BITS: usize;
+
+    /// The number of possible Hamming distances between two descriptors of this type.
+    ///
+    /// This is `BITS + 1` because both `0` and `BITS` are possible distances.
+    const DISTANCES: usize = Self::BITS + 1;
+}
+
+macro_rules! impl_integer_descriptor {
+    ($($t:ty),*) => {
+        $(
+            impl BinaryDescriptor for $t {
+                #[inline(always)]
+                fn bits(&self) -> usize {
+                    <Self as FixedBinaryDescriptor>::BITS
+                }
+
+                #[inline(always)]
+                fn hamming(&self, other: &Self) -> u32 {
+                    (self ^ other).count_ones()
+                }
+            }
+
+            impl FixedBinaryDescriptor for $t {
+                const BITS: usize = <$t>::BITS as usize;
+            }
+        )*
+    };
+}
+
+impl_integer_descriptor!(u8, u16, u32, u64, u128);
+
+impl<const N: usize> BinaryDescriptor for [u8; N] {
+    #[inline(always)]
+    fn bits(&self) -> usize {
+        Self::BITS
+    }
+
+    #[inline(always)]
+    fn hamming(&self, other: &Self) -> u32 {
+        popcount::hamming(self, other)
+    }
+
+    #[inline(always)]
+    fn hamming_bounded(&self, other: &Self, bound: u32) -> BoundedDistance {
+        popcount::hamming_bounded(self, other, bound)
+    }
+}
+
+impl<const N: usize> FixedBinaryDescriptor for [u8; N] {
+    const BITS: usize = N * 8;
+}
+
+impl<const N: usize> BinaryDescriptor for [u64; N] {
+    #[inline(always)]
+    fn bits(&self) -> usize {
+        Self::BITS
+    }
+
+    #[inline(always)]
+    fn hamming(&self, other: &Self) -> u32 {
+        popcount::hamming_words(self, other)
+    }
+
+    #[inline(always)]
+    fn hamming_bounded(&self, other: &Self, bound: u32) -> BoundedDistance {
+        popcount::hamming_words_bounded(self, other, bound)
+    }
+}
+
+impl<const N: usize> FixedBinaryDescriptor for [u64; N] {
+    const BITS: usize = N * 64;
+}
+
+impl BinaryDescriptor for [u8] {
+    #[inline(always)]
+    fn bits(&self) -> usize {
+        self.len() * 8
+    }
+
+    #[inline(always)]
+    fn hamming(&self, other: &Self) -> u32 {
+        assert_eq!(self.len(), other.len(), "descriptors differ in length");
+        popcount::hamming(self, other)
+    }
+
+    #[inline(always)]
+    fn hamming_bounded(&self, other: &Self, bound: u32) -> BoundedDistance {
+        assert_eq!(self.len(), other.len(), "descriptors differ in length");
+        popcount::hamming_bounded(self, other, bound)
+    }
+}
+
+impl BinaryDescriptor for [u64] {
+    #[inline(always)]
+    fn bits(&self) -> usize {
+        self.len() * 64
+    }
+
+    #[inline(always)]
+    fn hamming(&self, other: &Self) -> u32 {
+        assert_eq!(self.len(), other.len(), "descriptors differ in length");
+        popcount::hamming_words(self, other)
+    }
+
+    #[inline(always)]
+    fn hamming_bounded(&self, other: &Self, bound: u32) -> BoundedDistance {
+        assert_eq!(self.len(), other.len(), "descriptors differ in length");
+        popcount::hamming_words_bounded(self, other, bound)
+    }
+}
+
+/// Computes the distance between two descriptors as a `D`.
+///
+/// Returns an error if the distance can't be represented by `D`, in which case it is also outside of the
+/// `distances` that the heap could have been configured with.
+#[inline(always)]
+pub(crate) fn distance_between<D, X>(
+    query: &X,
+    feature: &X,
+    distances: usize,
+) -> Result<D, HammingHeapError>
+where
+    D: Distance,
+    X: BinaryDescriptor + ?Sized,
+{
+    from_hamming(query.hamming(feature), distances)
+}
+
+/// Converts a Hamming distance to a `D`, returning an error if it can't be represented.
+#[inline(always)]
+pub(crate) fn from_hamming<D: Distance>(
+    distance: u32,
+    distances: usize,
+) -> Result<D, HammingHeapError> {
+    let distance = distance as usize;
+    D::from_index(distance).ok_or(HammingHeapError::DistanceOutOfRange {
+        distance,
+        distances,
+    })
+}
+
+#[cfg(test)]
+#[test]
+fn test_descriptors() {
+    assert_eq!(<u128 as FixedBinaryDescriptor>::DISTANCES, 129);
+    assert_eq!(<[u8; 61] as FixedBinaryDescriptor>::BITS, 488);
+    assert_eq!(<[u64; 4] as FixedBinaryDescriptor>::DISTANCES, 257);
+
+    let mut a = [0u8; 61];
+    let mut b = [0u8; 61];
+    for ix in 0..61 {
+        a[ix] = (ix * 37) as u8;
+        b[ix] = (ix * 91 + 5) as u8;
+    }
+    let expected: u32 = a.iter().zip(&b).map(|(a, b)| (a ^ b).count_ones()).sum();
+    assert_eq!(a.hamming(&b), expected);
+    assert_eq!(a[..].hamming(&b[..]), expected);
+    assert_eq!(a.hamming(&a), 0);
+    assert_eq!([!0u64; 3].hamming(&[0; 3]), 192);
+    assert_eq!(0xf0u8.hamming(&0x0f), 8);
+}
+
+#[cfg(test)]
+#[test]
+#[should_panic]
+fn test_descriptor_length_mismatch() {
+    [0u8; 4][..].hamming(&[0u8; 5][..]);
+}
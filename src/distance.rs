@@ -0,0 +1,141 @@
+use crate::HammingHeapError;
+use core::fmt::Debug;
+use core::ops::{Bound, Range, RangeBounds};
+
+/// A bounded integer key that the heaps can use as a distance.
+///
+/// Each distance maps to the index of a bucket, so any small non-negative integer metric can be used, not only
+/// popcounts. Smaller keys like `u8` keep the `(distance, item)` tuples small when descriptors have fewer than
+/// `256` bits.
+///
+/// The heaps check that every distance they were configured with can be converted back, so `from_index` is only
+/// called with indices below the configured number of distances.
+///
+/// A newtype can be used as a key by implementing this trait:
+///
+/// ```
+/// use hamming_heap::{Distance, HammingHeap};
+///
+/// #[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+/// struct Level(u8);
+///
+/// impl Distance for Level {
+///     fn to_index(self) -> usize {
+///         self.0 as usize
+///     }
+///
+///     fn from_index(index: usize) -> Option<Self> {
+///         u8::from_index(index).map(Level)
+///     }
+/// }
+///
+/// let mut candidates: HammingHeap<&str, Level> = HammingHeap::default();
+/// candidates.set_distances(5);
+/// candidates.push(Level(3), "coarse");
+/// candidates.push(Level(1), "fine");
+/// assert_eq!(candidates.pop(), Some((Level(1), "fine")));
+/// ```
+pub trait Distance: Copy + Ord + Debug {
+    /// Gets the index of the bucket for this distance.
+    fn to_index(self) -> usize;
+
+    /// Gets the distance for a bucket index, or `None` if this type can't represent it.
+    fn from_index(index: usize) -> Option<Self>;
+}
+
+macro_rules! impl_distance {
+    ($($t:ty),*) => {
+        $(
+            impl Distance for $t {
+                #[inline(always)]
+                fn to_index(self) -> usize {
+                    self as usize
+                }
+
+                #[inline(always)]
+                fn from_index(index: usize) -> Option<Self> {
+                    use core::convert::TryFrom;
+                    <$t>::try_from(index).ok()
+                }
+            }
+        )*
+    };
+}
+
+impl_distance!(u8, u16, u32, u64, usize);
+
+/// Converts a bucket index that the heap has already checked back into a distance.
+#[inline(always)]
+pub(crate) fn from_index<D: Distance>(index: usize) -> D {
+    D::from_index(index).expect("bucket index was not representable by the distance type")
+}
+
+/// Mirrors a distance that the heap has already checked within `distances`, so the largest distance maps to `0`.
+///
+/// Mirroring twice gives back the original distance.
+#[inline(always)]
+pub(crate) fn flip<D: Distance>(distance: D, distances: usize) -> D {
+    from_index(distances - 1 - distance.to_index())
+}
+
+/// Checks that every distance below `distances` is representable by `D`.
+pub(crate) fn check_distances<D: Distance>(distances: usize) -> Result<(), HammingHeapError> {
+    if distances == 0 || D::from_index(distances - 1).is_some() {
+        Ok(())
+    } else {
+        Err(HammingHeapError::TooManyDistances { distances })
+    }
+}
+
+/// Gets the bucket index of `distance` if it is within the configured `distances`.
+#[inline(always)]
+pub(crate) fn check_index<D: Distance>(
+    distance: D,
+    distances: usize,
+) -> Result<usize, HammingHeapError> {
+    let index = distance.to_index();
+    if index < distances {
+        Ok(index)
+    } else {
+        Err(index_error(index, distances))
+    }
+}
+
+/// Gets the error for a bucket index `index` that is not within the configured `distances`.
+pub(crate) fn index_error(index: usize, distances: usize) -> HammingHeapError {
+    if distances == 0 {
+        HammingHeapError::DistancesNotSet
+    } else {
+        HammingHeapError::DistanceOutOfRange {
+            distance: index,
+            distances,
+        }
+    }
+}
+
+/// Converts a range of distances into the range of bucket indices it covers within `distances` buckets.
+pub(crate) fn index_range<D: Distance, R: RangeBounds<D>>(
+    range: &R,
+    distances: usize,
+) -> Range<usize> {
+    let start = match range.start_bound() {
+        Bound::Included(&distance) => distance.to_index(),
+        Bound::Excluded(&distance) => distance.to_index().saturating_add(1),
+        Bound::Unbounded => 0,
+    };
+    let end = match range.end_bound() {
+        Bound::Included(&distance) => distance.to_index().saturating_add(1),
+        Bound::Excluded(&distance) => distance.to_index(),
+        Bound::Unbounded => distances,
+    };
+    let end = core::cmp::min(end, distances);
+    core::cmp::min(start, end)..end
+}
+
+#[cfg(test)]
+#[test]
+#[should_panic]
+fn test_unrepresentable_distances() {
+    let mut candidates: crate::HammingHeap<(), u8> = crate::HammingHeap::default();
+    candidates.set_distances(257);
+}
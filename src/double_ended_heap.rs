@@ -0,0 +1,415 @@
+use crate::buckets::{Buckets, VecBuckets};
+use crate::distance::{self, Distance};
+use crate::fixed_heap::PushResult;
+use crate::iter::Iter;
+use crate::occupancy::Occupancy;
+use crate::tie_break::{End, TieBreak};
+use crate::HammingHeapError;
+use core::marker::PhantomData;
+
+/// A bucket queue that can be popped from both ends, with an optional capacity.
+///
+/// This combines the behaviour of [`HammingHeap`](crate::HammingHeap) and
+/// [`FixedHammingHeap`](crate::FixedHammingHeap): the nearest and farthest items can both be peeked at in constant
+/// time and popped, and once a capacity is set, pushing a better item evicts the farthest one. A beam search can
+/// take candidates from the best end and prune from the worst end of one structure.
+///
+/// ```
+/// use hamming_heap::DoubleEndedHammingHeap;
+/// let mut beam = DoubleEndedHammingHeap::new_distances(129);
+/// beam.set_capacity(Some(3));
+/// for &(distance, node) in &[(40, 'a'), (12, 'b'), (90, 'c'), (33, 'd')] {
+///     beam.push(distance, node);
+/// }
+/// assert_eq!(beam.peek_min(), Some((12, &'b')));
+/// assert_eq!(beam.peek_max(), Some((40, &'a')));
+/// assert_eq!(beam.pop_min(), Some((12, 'b')));
+/// assert_eq!(beam.pop_max(), Some((40, 'a')));
+/// assert_eq!(beam.len(), 1);
+/// ```
+#[derive(Clone, Debug)]
+pub struct DoubleEndedHammingHeap<T, D = u32, B = VecBuckets<T>> {
+    distances: B,
+    occupied: Occupancy,
+    /// The smallest occupied distance, if the heap is not empty.
+    min: usize,
+    /// The largest occupied distance, if the heap is not empty.
+    max: usize,
+    len: usize,
+    cap: Option<usize>,
+    tie_break: TieBreak<T>,
+    _marker: PhantomData<(T, D)>,
+}
+
+impl<T> DoubleEndedHammingHeap<T> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Automatically initializes self with `distances` distances.
+    pub fn new_distances(distances: usize) -> Self {
+        let mut s = Self::new();
+        s.set_distances(distances);
+        s
+    }
+}
+
+impl<T, D, B> DoubleEndedHammingHeap<T, D, B>
+where
+    D: Distance,
+    B: Buckets<T>,
+{
+    /// This allows the queue to be cleared so that we don't need to reallocate memory.
+    pub fn clear(&mut self) {
+        for distance in self.occupied.iter() {
+            self.distances.clear(distance);
+        }
+        self.occupied.clear();
+        self.min = 0;
+        self.max = 0;
+        self.len = 0;
+    }
+
+    /// Gets the number of items in the heap.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Checks if the heap is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Set number of distances. Also clears the heap.
+    ///
+    /// Panics if the largest distance can't be represented by `D`.
+    pub fn set_distances(&mut self, distances: usize) {
+        if let Err(e) = self.try_set_distances(distances) {
+            panic!("{}", e);
+        }
+    }
+
+    /// Set number of distances. Also clears the heap.
+    ///
+    /// Returns an error and leaves the heap unchanged if the largest distance can't be represented by `D`.
+    pub fn try_set_distances(&mut self, distances: usize) -> Result<(), HammingHeapError> {
+        distance::check_distances::<D>(distances)?;
+        self.distances.reset(distances);
+        self.occupied.reset(distances);
+        self.min = 0;
+        self.max = 0;
+        self.len = 0;
+        Ok(())
+    }
+
+    /// Sets the capacity, or removes it with `None`. If the capacity is lowered below the length, the farthest
+    /// items are removed.
+    ///
+    /// Panics if the capacity is `Some(0)`.
+    pub fn set_capacity(&mut self, cap: Option<usize>) {
+        if let Err(e) = self.try_set_capacity(cap) {
+            panic!("{}", e);
+        }
+    }
+
+    /// Sets the capacity, or removes it with `None`, like `set_capacity`.
+    ///
+    /// Returns an error and leaves the heap unchanged if the capacity is `Some(0)`.
+    pub fn try_set_capacity(&mut self, cap: Option<usize>) -> Result<(), HammingHeapError> {
+        if cap == Some(0) {
+            return Err(HammingHeapError::ZeroCapacity);
+        }
+        self.cap = cap;
+        if let Some(cap) = cap {
+            while self.len > cap {
+                self.remove(self.max, End::Worst);
+            }
+        }
+        Ok(())
+    }
+
+    /// Gets the capacity, if one was set.
+    pub fn capacity(&self) -> Option<usize> {
+        self.cap
+    }
+
+    /// Returns true if a capacity was set and it has been reached.
+    pub fn at_cap(&self) -> bool {
+        self.cap.is_some_and(|cap| self.len >= cap)
+    }
+
+    /// Sets the policy that decides which of the items tied at a distance is popped or evicted first.
+    pub fn set_tie_break(&mut self, tie_break: TieBreak<T>) {
+        tie_break.sort(&mut self.distances);
+        self.tie_break = tie_break;
+    }
+
+    /// Gets the policy that decides which of the items tied at a distance is popped or evicted first.
+    pub fn tie_break(&self) -> TieBreak<T> {
+        self.tie_break
+    }
+
+    /// Inserts an item.
+    ///
+    /// Returns true if it was added, which is only false if the heap is at the cap and `distance` is no better
+    /// than the farthest item.
+    ///
+    /// Panics if `distance` is outside of the configured distances.
+    pub fn push(&mut self, distance: D, item: T) -> bool {
+        match self.try_push(distance, item) {
+            Ok(added) => added,
+            Err(e) => panic!("{}", e),
+        }
+    }
+
+    /// Inserts an item.
+    ///
+    /// Returns `Ok(true)` if it was added. Returns an error and drops the item if `distance` is outside of the
+    /// configured distances.
+    pub fn try_push(&mut self, distance: D, item: T) -> Result<bool, HammingHeapError> {
+        self.try_push_evict(distance, item)
+            .map(|result| !matches!(result, PushResult::Rejected(_)))
+    }
+
+    /// Inserts an item, handing back the item that doesn't stay in the heap if it is at the cap.
+    ///
+    /// Panics if `distance` is outside of the configured distances.
+    pub fn push_evict(&mut self, distance: D, item: T) -> PushResult<T, D> {
+        match self.try_push_evict(distance, item) {
+            Ok(result) => result,
+            Err(e) => panic!("{}", e),
+        }
+    }
+
+    /// Inserts an item, handing back the item that doesn't stay in the heap if it is at the cap.
+    ///
+    /// Returns an error and drops the item if `distance` is outside of the configured distances.
+    pub fn try_push_evict(
+        &mut self,
+        distance: D,
+        item: T,
+    ) -> Result<PushResult<T, D>, HammingHeapError> {
+        let distance = distance::check_index(distance, self.distances.num_buckets())?;
+        if !self.at_cap() {
+            self.insert(distance, item);
+            Ok(PushResult::Added)
+        } else if distance < self.max {
+            self.insert(distance, item);
+            let max = self.max;
+            let evicted = self.remove(max, End::Worst);
+            Ok(PushResult::Evicted(distance::from_index(max), evicted))
+        } else {
+            Ok(PushResult::Rejected(item))
+        }
+    }
+
+    /// Gets the smallest distance if not empty.
+    pub fn min(&self) -> Option<D> {
+        if self.is_empty() {
+            None
+        } else {
+            Some(distance::from_index(self.min))
+        }
+    }
+
+    /// Gets the largest distance if not empty.
+    pub fn max(&self) -> Option<D> {
+        if self.is_empty() {
+            None
+        } else {
+            Some(distance::from_index(self.max))
+        }
+    }
+
+    /// Gets the item that `pop_min` would remove.
+    pub fn peek_min(&self) -> Option<(D, &T)> {
+        self.peek(self.min, End::Best)
+    }
+
+    /// Gets the item that `pop_max` would remove.
+    pub fn peek_max(&self) -> Option<(D, &T)> {
+        self.peek(self.max, End::Worst)
+    }
+
+    /// Removes an item at the smallest distance.
+    pub fn pop_min(&mut self) -> Option<(D, T)> {
+        if self.is_empty() {
+            return None;
+        }
+        let min = self.min;
+        Some((distance::from_index(min), self.remove(min, End::Best)))
+    }
+
+    /// Removes an item at the largest distance.
+    pub fn pop_max(&mut self) -> Option<(D, T)> {
+        if self.is_empty() {
+            return None;
+        }
+        let max = self.max;
+        Some((distance::from_index(max), self.remove(max, End::Worst)))
+    }
+
+    /// Iterate over the entire queue in best-to-worse order.
+    pub fn iter(&self) -> Iter<'_, T, D, B> {
+        let range = if self.is_empty() {
+            0..0
+        } else {
+            self.min..self.max + 1
+        };
+        Iter::new(&self.distances, &self.occupied, range, self.len)
+    }
+
+    /// Gets the item that the tie-breaking policy would remove from the `end` of the bucket `distance`.
+    fn peek(&self, distance: usize, end: End) -> Option<(D, &T)> {
+        if self.is_empty() {
+            return None;
+        }
+        let items = self.distances.bucket(distance);
+        // Selecting with a copy leaves a random policy in the state that the next pop selects with.
+        let mut tie_break = self.tie_break;
+        let index = tie_break.select(items, end)?;
+        Some((distance::from_index(distance), &items[index]))
+    }
+
+    /// Adds an item at the bucket index `distance`, which must be in range.
+    fn insert(&mut self, distance: usize, item: T) {
+        self.tie_break.insert(&mut self.distances, distance, item);
+        self.occupied.insert(distance);
+        if self.len == 0 {
+            self.min = distance;
+            self.max = distance;
+        } else if distance < self.min {
+            self.min = distance;
+        } else if distance > self.max {
+            self.max = distance;
+        }
+        self.len += 1;
+    }
+
+    /// Removes an item from the `end` of the occupied bucket `distance` and moves the ends past it if it empties.
+    fn remove(&mut self, distance: usize, end: End) -> T {
+        let item = self
+            .tie_break
+            .take(&mut self.distances, distance, end)
+            .expect("occupied bucket was empty");
+        self.len -= 1;
+        if self.distances.bucket(distance).is_empty() {
+            self.occupied.remove(distance);
+            if self.len != 0 {
+                if distance == self.min {
+                    self.min = self
+                        .occupied
+                        .first_from(distance)
+                        .expect("heap had fewer items than its length");
+                }
+                if distance == self.max {
+                    self.max = self
+                        .occupied
+                        .last_to(distance)
+                        .expect("heap had fewer items than its length");
+                }
+            }
+        }
+        item
+    }
+}
+
+impl<T, D, B> Default for DoubleEndedHammingHeap<T, D, B>
+where
+    D: Distance,
+    B: Buckets<T>,
+{
+    fn default() -> Self {
+        Self {
+            distances: B::default(),
+            occupied: Occupancy::default(),
+            min: 0,
+            max: 0,
+            len: 0,
+            cap: None,
+            tie_break: TieBreak::default(),
+            _marker: PhantomData,
+        }
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn test_double_ended_against_reference() {
+    use crate::testing::{insert_sorted, XorShift};
+
+    let mut rng = XorShift::new(0x6a09_e667_f3bc_c909);
+    let mut candidates: DoubleEndedHammingHeap<u32, u16> = DoubleEndedHammingHeap::default();
+    candidates.set_distances(200);
+    // The reference holds the sorted distances that should be in the heap.
+    let mut reference: Vec<u16> = vec![];
+    let mut cap = None;
+    for _ in 0..5000 {
+        match rng.below(10) {
+            0 => {
+                cap = if rng.below(3) == 0 {
+                    None
+                } else {
+                    Some(1 + rng.below(20) as usize)
+                };
+                candidates.set_capacity(cap);
+                if let Some(cap) = cap {
+                    reference.truncate(cap);
+                }
+            }
+            1 | 2 => {
+                let popped = candidates.pop_min();
+                assert_eq!(
+                    popped.map(|(d, item)| (d, item as u16)),
+                    reference.first().map(|&d| (d, d))
+                );
+                if !reference.is_empty() {
+                    reference.remove(0);
+                }
+            }
+            3 => {
+                assert_eq!(candidates.pop_max().map(|(d, _)| d), reference.pop());
+            }
+            _ => {
+                let distance = rng.below(200) as u16;
+                let accepted = cap.map_or(true, |cap| {
+                    reference.len() < cap || distance < *reference.last().unwrap()
+                });
+                assert_eq!(candidates.push(distance, distance.into()), accepted);
+                if accepted {
+                    insert_sorted(&mut reference, distance);
+                    if let Some(cap) = cap {
+                        reference.truncate(cap);
+                    }
+                }
+            }
+        }
+        assert_eq!(candidates.len(), reference.len());
+        assert_eq!(candidates.min(), reference.first().copied());
+        assert_eq!(candidates.max(), reference.last().copied());
+        assert_eq!(
+            candidates.peek_min().map(|(d, _)| d),
+            reference.first().copied()
+        );
+        assert_eq!(
+            candidates.peek_max().map(|(d, _)| d),
+            reference.last().copied()
+        );
+    }
+    let distances: Vec<u16> = candidates.iter().map(|(d, _)| d).collect();
+    assert_eq!(distances, reference);
+
+    // Peeking must agree with the next pop even when ties are broken randomly.
+    candidates.set_capacity(None);
+    candidates.set_tie_break(TieBreak::Random(3));
+    for item in 0..20 {
+        candidates.push(7, 1000 + item);
+        candidates.push(150, 2000 + item);
+    }
+    while !candidates.is_empty() {
+        let peeked = candidates.peek_max().map(|(d, &item)| (d, item));
+        assert_eq!(candidates.pop_max(), peeked);
+        let peeked = candidates.peek_min().map(|(d, &item)| (d, item));
+        assert_eq!(candidates.pop_min(), peeked);
+    }
+}
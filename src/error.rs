@@ -0,0 +1,97 @@
+use core::fmt;
+
+/// The ways a heap can be misused.
+///
+/// The panicking methods on the heaps panic with these errors, and the `try_` methods return them instead.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum HammingHeapError {
+    /// The heap has no distances because `set_distances` was never called.
+    DistancesNotSet,
+    /// A distance was outside of the configured number of distances.
+    DistanceOutOfRange {
+        /// The index of the distance that was given.
+        distance: usize,
+        /// The configured number of distances.
+        distances: usize,
+    },
+    /// The number of distances can't be represented by the distance type.
+    TooManyDistances {
+        /// The number of distances that was requested.
+        distances: usize,
+    },
+    /// A capacity of `0` was requested.
+    ZeroCapacity,
+}
+
+impl fmt::Display for HammingHeapError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            HammingHeapError::DistancesNotSet => {
+                write!(f, "you must call set_distances() before using the heap")
+            }
+            HammingHeapError::DistanceOutOfRange {
+                distance,
+                distances,
+            } => write!(
+                f,
+                "distance {} is out of range for a heap with {} distances",
+                distance, distances
+            ),
+            HammingHeapError::TooManyDistances { distances } => write!(
+                f,
+                "{} distances can't be represented by the distance type",
+                distances
+            ),
+            HammingHeapError::ZeroCapacity => write!(f, "capacity must be non-zero"),
+        }
+    }
+}
+
+impl std::error::Error for HammingHeapError {}
+
+#[cfg(test)]
+#[test]
+fn test_errors() {
+    use crate::{FixedHammingHeap, HammingHeap};
+
+    let mut candidates: HammingHeap<()> = HammingHeap::new();
+    assert_eq!(candidates.try_pop(), Err(HammingHeapError::DistancesNotSet));
+    assert_eq!(
+        candidates.try_push(0, ()),
+        Err(HammingHeapError::DistancesNotSet)
+    );
+    let mut candidates: HammingHeap<(), u8> = HammingHeap::default();
+    assert_eq!(
+        candidates.try_set_distances(300),
+        Err(HammingHeapError::TooManyDistances { distances: 300 })
+    );
+
+    let mut candidates: FixedHammingHeap<()> = FixedHammingHeap::new();
+    assert_eq!(
+        candidates.try_clear(),
+        Err(HammingHeapError::DistancesNotSet)
+    );
+    assert_eq!(
+        candidates.try_set_capacity(3),
+        Err(HammingHeapError::DistancesNotSet)
+    );
+    candidates.set_distances(9);
+    assert_eq!(
+        candidates.try_push(4, ()),
+        Err(HammingHeapError::ZeroCapacity)
+    );
+    assert_eq!(
+        candidates.try_set_capacity(0),
+        Err(HammingHeapError::ZeroCapacity)
+    );
+    candidates.set_capacity(1);
+    assert_eq!(
+        candidates.try_push(9, ()),
+        Err(HammingHeapError::DistanceOutOfRange {
+            distance: 9,
+            distances: 9
+        })
+    );
+    assert_eq!(candidates.try_push(4, ()), Ok(true));
+    assert_eq!(candidates.try_push(5, ()), Ok(false));
+}
@@ -1,32 +1,64 @@
+use crate::buckets::{Buckets, VecBuckets};
+use crate::descriptor::{self, BinaryDescriptor, BoundedDistance, FixedBinaryDescriptor};
+use crate::distance::{self, Distance};
+use crate::iter::{BucketsIter, Iter, IterMut};
+use crate::occupancy::Occupancy;
+use crate::tie_break::{End, TieBreak};
+use crate::HammingHeapError;
+use core::cmp::Ordering;
+use core::marker::PhantomData;
+use core::ops::RangeBounds;
+
 /// This keeps the nearest `cap` items at all times.
 ///
-/// This heap is not intended to be popped. Instead, this maintains the best `cap` items, and then when you are
-/// done adding items, you may fill a slice or iterate over the results. Theoretically, this could also allow
-/// popping elements in constant time, but that would incur a performance penalty for the highly specialized
-/// purpose this serves. This is specifically tailored for doing hamming space nearest neighbor searches.
+/// This heap is mainly intended to maintain the best `cap` items, and then when you are done adding items, you may
+/// fill a slice or iterate over the results. It can also be popped from either end with `pop_best` and `pop_worst`
+/// or drained in sorted order, so one heap can serve as both the result set and a sorted candidate list in a beam
+/// search. This is specifically tailored for doing hamming space nearest neighbor searches.
 ///
-/// To use this you will need to call `set_distances` before use. This should be passed the maximum number of
-/// distances. Please keep in mind that the maximum number of hamming distances between an `n` bit number
-/// is `n + 1`. An example would be:
+/// To use this you will need to set the number of distances before use. The easiest way is to construct it with
+/// `for_descriptor`, which derives the number of distances from the width of a [`FixedBinaryDescriptor`], and to
+/// push with `push_descriptor`, which computes the Hamming distance for you:
 ///
 /// ```
-/// assert_eq!((0u128 ^ !0).count_ones(), 128);
+/// use hamming_heap::FixedHammingHeap;
+/// let mut candidates: FixedHammingHeap<()> = FixedHammingHeap::for_descriptor::<u128>();
+/// candidates.set_capacity(3);
+/// candidates.push_descriptor(&0u128, &!0u128, ());
 /// ```
 ///
-/// So make sure you use `n + 1` as your `distances` or else you may encounter a runtime panic.
+/// When calling `set_distances` yourself, keep in mind that an `n` bit descriptor has `n + 1` distances, because
+/// both `0` and `n` are possible distances.
+///
+/// By default, items tied with the `cap`-th best distance are dropped according to the [`TieBreak`] policy so that
+/// the heap never holds more than `cap` items. With `set_keep_ties(true)` the heap keeps every item tied at that
+/// distance instead, so the results are exactly the items at the `cap` best distances:
 ///
 /// ```
 /// use hamming_heap::FixedHammingHeap;
 /// let mut candidates = FixedHammingHeap::new_distances(129);
-/// candidates.set_capacity(3);
-/// candidates.push((0u128 ^ !0u128).count_ones(), ());
+/// candidates.set_capacity(2);
+/// candidates.set_keep_ties(true);
+/// for &(distance, item) in &[(4, 'a'), (9, 'b'), (4, 'c'), (4, 'd'), (1, 'e')] {
+///     candidates.push(distance, item);
+/// }
+/// assert_eq!(candidates.len(), 4);
+/// assert_eq!(candidates.tie_group(), Some((4, &['a', 'c', 'd'][..])));
 /// ```
+///
+/// Distances are `u32` by default, which is what `count_ones()` returns. Any [`Distance`] can be used instead.
+///
+/// The items are stored in [`VecBuckets`] by default. See [`Buckets`] for the other storage backends.
 #[derive(Clone, Debug)]
-pub struct FixedHammingHeap<T> {
+pub struct FixedHammingHeap<T, D = u32, B = VecBuckets<T>> {
     cap: usize,
     size: usize,
-    worst: u32,
-    distances: Vec<Vec<T>>,
+    worst: usize,
+    distances: B,
+    occupied: Occupancy,
+    tie_break: TieBreak<T>,
+    keep_ties: bool,
+    _marker: PhantomData<(T, D)>,
 }
 
 impl<T> FixedHammingHeap<T> {
@@ -41,51 +73,146 @@ impl<T> FixedHammingHeap<T> {
         s
     }
 
+    /// Automatically initializes self with every distance possible between two `X` descriptors.
+    ///
+    /// Panics if the largest distance can't be represented by `D`.
+    pub fn for_descriptor<X>() -> Self
+    where
+        X: FixedBinaryDescriptor,
+    {
+        Self::new_distances(X::DISTANCES)
+    }
+}
+
+impl<T, D, B> FixedHammingHeap<T, D, B>
+where
+    D: Distance,
+    B: Buckets<T>,
+{
     /// This sets the capacity of the queue to `cap`, meaning that adding items to the queue will eject the worst ones
     /// if they are better once `cap` is reached. If the capacity is lowered, this removes the worst elements to
-    /// keep `size == cap`.
+    /// keep `size == cap`, or only the distances beyond the `cap`-th best distance if ties are kept.
+    ///
+    /// Panics if `cap` is `0` or if `set_distances` was never called.
     pub fn set_capacity(&mut self, cap: usize) {
-        assert_ne!(cap, 0);
-        self.set_len(cap);
+        if let Err(e) = self.try_set_capacity(cap) {
+            panic!("{}", e);
+        }
+    }
+
+    /// This sets the capacity of the queue to `cap` like `set_capacity`.
+    ///
+    /// Returns an error and leaves the heap unchanged if `cap` is `0` or if `set_distances` was never called.
+    pub fn try_set_capacity(&mut self, cap: usize) -> Result<(), HammingHeapError> {
+        if cap == 0 {
+            return Err(HammingHeapError::ZeroCapacity);
+        }
+        if self.distances.num_buckets() == 0 {
+            return Err(HammingHeapError::DistancesNotSet);
+        }
+        if !self.keep_ties {
+            self.set_len(cap);
+        }
         self.cap = cap;
-        // After the capacity is changed, if the size now equals the capacity we need to update the worst because it must
-        // actually be set to the worst item.
-        self.worst = self.distances.len() as u32 - 1;
-        if self.size == self.cap {
-            self.update_worst();
+        // After the capacity is changed, if the size now reaches the capacity we need to update the worst because it
+        // must actually be set to the worst item.
+        self.reset_worst();
+        self.drop_excess_ties();
+        Ok(())
+    }
+
+    /// Sets whether every item tied with the `cap`-th best distance is kept, which lets the length exceed `cap`.
+    ///
+    /// While ties are kept, the heap is at the cap once it holds at least `cap` items, and `worst` is the `cap`-th
+    /// best distance. Items at `worst` are still accepted, and the items at `worst` are dropped together once there
+    /// are `cap` items strictly better than them. If ties stop being kept, the worst elements are removed to keep
+    /// `size <= cap`.
+    pub fn set_keep_ties(&mut self, keep_ties: bool) {
+        self.keep_ties = keep_ties;
+        if !keep_ties && self.size > self.cap {
+            self.set_len(self.cap);
         }
     }
 
-    /// This removes elements until it reaches `len`. If `len` is higher than the current
+    /// Checks whether every item tied with the `cap`-th best distance is kept.
+    pub fn keeps_ties(&self) -> bool {
+        self.keep_ties
+    }
+
+    /// This removes the worst elements until it reaches `len`. If `len` is higher than the current
     /// number of elements, this does nothing. If the len is lowered, this will unconditionally allow insertions
     /// until `cap` is reached.
     pub fn set_len(&mut self, len: usize) {
         if len == 0 {
-            let end = self.end();
-            for v in &mut self.distances[..=end] {
-                v.clear();
-            }
-            self.size = 0;
-            self.worst = self.distances.len() as u32 - 1;
+            self.clear();
         } else if len < self.size {
-            // Remove the difference between them.
-            let end = self.end();
+            // Remove the difference between them, starting from the worst distance.
             let mut remaining = self.size - len;
-            for vec in &mut self.distances[..=end] {
-                if vec.len() >= remaining {
-                    // This has enough, remove them then break.
-                    vec.drain(vec.len() - remaining..);
+            let mut end = self.end();
+            while remaining != 0 {
+                let worst = self
+                    .occupied
+                    .last_to(end)
+                    .expect("heap had fewer elements than its size");
+                let bucket_len = self.distances.bucket(worst).len();
+                if bucket_len > remaining {
+                    // This has enough, remove them then stop.
+                    if let TieBreak::Lifo = self.tie_break {
+                        self.distances.truncate(worst, bucket_len - remaining);
+                    } else {
+                        for _ in 0..remaining {
+                            self.evict(worst);
+                        }
+                    }
                     break;
-                } else {
-                    // There werent enough, so remove everything and move on.
-                    remaining -= vec.len();
-                    vec.clear();
                 }
+                // There werent enough, so remove everything and move on.
+                remaining -= bucket_len;
+                self.distances.clear(worst);
+                self.occupied.remove(worst);
+                end = worst;
             }
             // When len is less than the cap, worst must be set to max.
-            self.worst = self.distances.len() as u32 - 1;
             self.size = len;
+            self.reset_worst();
+        }
+    }
+
+    /// This removes the worst elements until it reaches `len` like `set_len`, but returns the removed
+    /// elements instead of dropping them.
+    ///
+    /// The removed elements are returned in the order they were removed, which is worse-to-best.
+    pub fn truncate_returning(&mut self, len: usize) -> Vec<(D, T)> {
+        let mut removed = Vec::with_capacity(self.size.saturating_sub(len));
+        while self.size > len {
+            removed.push(
+                self.pop_worst()
+                    .expect("heap had fewer elements than its size"),
+            );
+        }
+        removed
+    }
+
+    /// This removes every element with a distance worse than `distance`.
+    ///
+    /// If anything is removed, the heap is no longer at the cap, so `worst` is set back to max
+    /// until the cap is reached again. If ties are kept, enough elements may remain to stay at the cap, in which
+    /// case `worst` becomes the new `cap`-th best distance.
+    pub fn truncate_beyond(&mut self, distance: D) {
+        let end = self.end();
+        let mut next = self
+            .occupied
+            .first_in(distance.to_index().saturating_add(1), end);
+        if next.is_none() {
+            return;
+        }
+        while let Some(beyond) = next {
+            self.size -= self.distances.bucket(beyond).len();
+            self.distances.clear(beyond);
+            self.occupied.remove(beyond);
+            next = self.occupied.first_in(beyond + 1, end);
         }
+        self.reset_worst();
     }
 
     /// Gets the `len` or `size` of the heap.
@@ -99,18 +226,28 @@ impl<T> FixedHammingHeap<T> {
     }
 
     /// Clear the queue while maintaining the allocated memory.
+    ///
+    /// Panics if `set_distances` was never called.
     pub fn clear(&mut self) {
-        assert_ne!(
-            self.distances.len(),
-            0,
-            "you must call set_distances() before calling clear()"
-        );
-        let end = self.end();
-        for v in self.distances[..=end].iter_mut() {
-            v.clear();
+        if let Err(e) = self.try_clear() {
+            panic!("{}", e);
+        }
+    }
+
+    /// Clear the queue while maintaining the allocated memory.
+    ///
+    /// Returns an error if `set_distances` was never called.
+    pub fn try_clear(&mut self) -> Result<(), HammingHeapError> {
+        if self.distances.num_buckets() == 0 {
+            return Err(HammingHeapError::DistancesNotSet);
+        }
+        for distance in self.occupied.iter() {
+            self.distances.clear(distance);
         }
+        self.occupied.clear();
         self.size = 0;
-        self.worst = self.distances.len() as u32 - 1;
+        self.worst = self.distances.num_buckets() - 1;
+        Ok(())
     }
 
     /// Set number of distances. Also clears the heap.
@@ -119,128 +256,815 @@ impl<T> FixedHammingHeap<T> {
     ///
     /// If you have a 128-bit number, keep in mind that it has `129` distances because
     /// `128` is one of the possible distances.
+    ///
+    /// Panics if the largest distance can't be represented by `D`.
     pub fn set_distances(&mut self, distances: usize) {
-        self.distances.clear();
-        self.distances.resize_with(distances, || vec![]);
-        self.worst = self.distances.len() as u32 - 1;
+        if let Err(e) = self.try_set_distances(distances) {
+            panic!("{}", e);
+        }
+    }
+
+    /// Set number of distances. Also clears the heap.
+    ///
+    /// Returns an error and leaves the heap unchanged if the largest distance can't be represented by `D`.
+    pub fn try_set_distances(&mut self, distances: usize) -> Result<(), HammingHeapError> {
+        distance::check_distances::<D>(distances)?;
+        self.distances.reset(distances);
+        self.occupied.reset(distances);
+        self.worst = distances.saturating_sub(1);
         self.size = 0;
+        Ok(())
+    }
+
+    /// Set every distance possible between two `X` descriptors. Also clears the heap.
+    ///
+    /// This is `for_descriptor` for heaps that were constructed another way, such as with a `D` other than `u32`.
+    ///
+    /// Panics if the largest distance can't be represented by `D`.
+    pub fn set_descriptor<X>(&mut self)
+    where
+        X: FixedBinaryDescriptor,
+    {
+        self.set_distances(X::DISTANCES);
+    }
+
+    /// Set every distance possible between two `X` descriptors. Also clears the heap.
+    ///
+    /// Returns an error and leaves the heap unchanged if the largest distance can't be represented by `D`.
+    pub fn try_set_descriptor<X>(&mut self) -> Result<(), HammingHeapError>
+    where
+        X: FixedBinaryDescriptor,
+    {
+        self.try_set_distances(X::DISTANCES)
     }
 
     /// Add a feature to the search.
     ///
     /// Returns true if it was added.
-    pub fn push(&mut self, distance: u32, item: T) -> bool {
-        if self.size != self.cap {
-            self.distances[distance as usize].push(item);
-            self.size += 1;
-            // Set the worst feature appropriately.
-            if self.size == self.cap {
-                self.update_worst();
-            }
+    ///
+    /// Panics if `distance` is outside of the configured distances or if the capacity was never set.
+    #[inline]
+    pub fn push(&mut self, distance: D, item: T) -> bool {
+        let distance = distance.to_index();
+        if distance >= self.distances.num_buckets() {
+            push_failed(distance::index_error(
+                distance,
+                self.distances.num_buckets(),
+            ));
+        }
+        if !self.at_cap() {
+            self.push_index_below_cap(distance, item);
             true
+        } else if self.cap == 0 {
+            push_failed(HammingHeapError::ZeroCapacity)
+        } else {
+            self.push_index_at_cap_added(distance, item)
+        }
+    }
+
+    /// Add a feature to the search.
+    ///
+    /// Returns `Ok(true)` if it was added. Returns an error and drops the item if `distance` is outside of the
+    /// configured distances or if the capacity was never set.
+    pub fn try_push(&mut self, distance: D, item: T) -> Result<bool, HammingHeapError> {
+        distance::check_index(distance, self.distances.num_buckets())?;
+        if self.cap == 0 {
+            return Err(HammingHeapError::ZeroCapacity);
+        }
+        Ok(self.push(distance, item))
+    }
+
+    /// Add a feature to the search at the Hamming distance between `query` and `feature`.
+    ///
+    /// Returns true if it was added.
+    ///
+    /// Panics if the distance is outside of the configured distances or if the capacity was never set.
+    pub fn push_descriptor<X>(&mut self, query: &X, feature: &X, item: T) -> bool
+    where
+        X: BinaryDescriptor + ?Sized,
+    {
+        match self.try_push_descriptor(query, feature, item) {
+            Ok(added) => added,
+            Err(e) => panic!("{}", e),
+        }
+    }
+
+    /// Add a feature to the search at the Hamming distance between `query` and `feature`.
+    ///
+    /// Returns `Ok(true)` if it was added. Returns an error and drops the item if the distance is outside of the
+    /// configured distances or if the capacity was never set.
+    pub fn try_push_descriptor<X>(
+        &mut self,
+        query: &X,
+        feature: &X,
+        item: T,
+    ) -> Result<bool, HammingHeapError>
+    where
+        X: BinaryDescriptor + ?Sized,
+    {
+        let distance = descriptor::distance_between(query, feature, self.distances.num_buckets())?;
+        self.try_push(distance, item)
+    }
+
+    /// Add a feature to the search at the Hamming distance between `query` and `feature`, abandoning the distance
+    /// computation as soon as it shows the feature can't be accepted.
+    ///
+    /// Once the heap is at the cap, the distance is accumulated a word at a time and given up when it reaches the
+    /// `worst` distance (or passes it, if ties are kept or the tie policy is `TieBreak::Order`). The item is then
+    /// dropped and `Abandoned` reports how many words were never looked at. Otherwise the distance is returned as
+    /// `Within` along with whether the item was added, which it might not be if it ties with the worst items.
+    ///
+    /// Checking the bound after every word is slower than the SIMD kernels used by `push_descriptor`, and with AVX2
+    /// it stays slower even when most features are abandoned half way through. Scanning 10,000 random 512-bit
+    /// descriptors with a capacity of 10 takes about 190µs this way against 130µs with `push_descriptor`. This
+    /// pays off on CPUs without AVX2, where both count the same way and the bound only ever saves work.
+    ///
+    /// ```
+    /// use hamming_heap::{BoundedDistance, FixedHammingHeap};
+    /// let mut candidates: FixedHammingHeap<char> = FixedHammingHeap::for_descriptor::<[u64; 4]>();
+    /// candidates.set_capacity(1);
+    /// let query = [0u64; 4];
+    /// assert_eq!(candidates.push_descriptor_bounded(&query, &[0, 0, 0, 7], 'a'), (BoundedDistance::Within(3), true));
+    /// assert_eq!(
+    ///     candidates.push_descriptor_bounded(&query, &[!0, 0, 0, 0], 'b'),
+    ///     (BoundedDistance::Abandoned { skipped_words: 3 }, false)
+    /// );
+    /// assert_eq!(candidates.into_sorted_vec(), vec![(3, 'a')]);
+    /// ```
+    ///
+    /// Panics if the distance is outside of the configured distances or if the capacity was never set.
+    pub fn push_descriptor_bounded<X>(
+        &mut self,
+        query: &X,
+        feature: &X,
+        item: T,
+    ) -> (BoundedDistance, bool)
+    where
+        X: BinaryDescriptor + ?Sized,
+    {
+        match self.try_push_descriptor_bounded(query, feature, item) {
+            Ok(pushed) => pushed,
+            Err(e) => panic!("{}", e),
+        }
+    }
+
+    /// Add a feature to the search at the Hamming distance between `query` and `feature`, abandoning the distance
+    /// computation as soon as it shows the feature can't be accepted.
+    ///
+    /// Returns the distance along with whether the item was added. Returns an error and drops the item if the
+    /// distance is outside of the configured distances or if the capacity was never set.
+    pub fn try_push_descriptor_bounded<X>(
+        &mut self,
+        query: &X,
+        feature: &X,
+        item: T,
+    ) -> Result<(BoundedDistance, bool), HammingHeapError>
+    where
+        X: BinaryDescriptor + ?Sized,
+    {
+        // Below the cap there is no bound, so the full kernel is used. Without a capacity it also lets `try_push`
+        // report the error.
+        let bounded = if self.cap == 0 || !self.at_cap() {
+            BoundedDistance::Within(query.hamming(feature))
+        } else if self.accepts_index(self.worst) {
+            query.hamming_bounded(feature, self.worst as u32 + 1)
         } else {
-            unsafe { self.push_at_cap(distance, item) }
+            query.hamming_bounded(feature, self.worst as u32)
+        };
+        let added = match bounded {
+            BoundedDistance::Within(distance) => {
+                let distance = descriptor::from_hamming(distance, self.distances.num_buckets())?;
+                self.try_push(distance, item)?
+            }
+            BoundedDistance::Abandoned { .. } => false,
+        };
+        Ok((bounded, added))
+    }
+
+    /// Add a feature to the search, handing back any item that doesn't stay in the heap.
+    ///
+    /// Unlike `push`, nothing is dropped: a rejected item is returned as `Rejected`, and the worst item is returned
+    /// as `Evicted` along with its distance when the new item displaces it. If ties are kept, the whole group at the
+    /// worst distance is displaced at once and returned as `EvictedTies`.
+    ///
+    /// ```
+    /// use hamming_heap::{FixedHammingHeap, PushResult};
+    /// let mut candidates = FixedHammingHeap::new_distances(9);
+    /// candidates.set_capacity(1);
+    /// assert_eq!(candidates.push_evict(5, 'a'), PushResult::Added);
+    /// assert_eq!(candidates.push_evict(7, 'b'), PushResult::Rejected('b'));
+    /// assert_eq!(candidates.push_evict(2, 'c'), PushResult::Evicted(5, 'a'));
+    /// ```
+    ///
+    /// Panics if `distance` is outside of the configured distances or if the capacity was never set.
+    pub fn push_evict(&mut self, distance: D, item: T) -> PushResult<T, D> {
+        match self.try_push_evict(distance, item) {
+            Ok(result) => result,
+            Err(e) => panic!("{}", e),
+        }
+    }
+
+    /// Add a feature to the search, handing back any item that doesn't stay in the heap.
+    ///
+    /// Returns an error and drops the item if `distance` is outside of the configured distances or if the capacity
+    /// was never set.
+    pub fn try_push_evict(
+        &mut self,
+        distance: D,
+        item: T,
+    ) -> Result<PushResult<T, D>, HammingHeapError> {
+        let distance = distance::check_index(distance, self.distances.num_buckets())?;
+        if !self.at_cap() {
+            self.push_index_below_cap(distance, item);
+            Ok(PushResult::Added)
+        } else if self.cap == 0 {
+            Err(HammingHeapError::ZeroCapacity)
+        } else {
+            Ok(self.push_index_at_cap(distance, item))
+        }
+    }
+
+    /// Checks if an item at `distance` would be kept by `push`.
+    ///
+    /// With an `Order` tie-breaking policy, an item at the worst distance is kept only if it orders before one of
+    /// the items tied there, so this returns true for the worst distance because the item might be kept.
+    ///
+    /// Returns false if `distance` is outside of the configured distances or if the capacity was never set.
+    pub fn would_accept(&self, distance: D) -> bool {
+        let distance = distance.to_index();
+        self.cap != 0 && distance < self.distances.num_buckets() && self.accepts_index(distance)
+    }
+
+    /// Add a feature to the search, only calling `make_item` if the feature will be kept.
+    ///
+    /// Most features in a linear scan are rejected once the heap is full, so this avoids building items that are
+    /// immediately dropped. Returns true if it was added.
+    ///
+    /// With an `Order` tie-breaking policy, a feature at the worst distance can only be judged by comparing the item
+    /// against the ones tied there, so `make_item` may be called for an item that is then rejected and dropped.
+    ///
+    /// ```
+    /// use hamming_heap::FixedHammingHeap;
+    /// let mut candidates = FixedHammingHeap::new_distances(9);
+    /// candidates.set_capacity(1);
+    /// assert!(candidates.push_with(3, || "near".to_owned()));
+    /// assert!(!candidates.push_with(5, || unreachable!()));
+    /// ```
+    ///
+    /// Panics if `distance` is outside of the configured distances or if the capacity was never set.
+    pub fn push_with<F>(&mut self, distance: D, make_item: F) -> bool
+    where
+        F: FnOnce() -> T,
+    {
+        match self.try_push_with(distance, make_item) {
+            Ok(added) => added,
+            Err(e) => panic!("{}", e),
+        }
+    }
+
+    /// Add a feature to the search, only calling `make_item` if the feature will be kept.
+    ///
+    /// Returns `Ok(true)` if it was added. Returns an error without calling `make_item` if `distance` is outside of
+    /// the configured distances or if the capacity was never set.
+    pub fn try_push_with<F>(&mut self, distance: D, make_item: F) -> Result<bool, HammingHeapError>
+    where
+        F: FnOnce() -> T,
+    {
+        let index = distance::check_index(distance, self.distances.num_buckets())?;
+        if self.cap == 0 {
+            return Err(HammingHeapError::ZeroCapacity);
+        }
+        if !self.accepts_index(index) {
+            return Ok(false);
+        }
+        let item = make_item();
+        if !self.at_cap() {
+            self.push_index_below_cap(index, item);
+            Ok(true)
+        } else {
+            // This compares the built item against the worst tie before inserting it.
+            Ok(self.push_index_at_cap_added(index, item))
+        }
+    }
+
+    /// Removes the best element.
+    ///
+    /// Since the heap is no longer at the cap afterwards, `worst` goes back to max until the cap is reached again.
+    /// If ties are kept, the heap can still be at the cap, in which case `worst` stays the `cap`-th best distance.
+    pub fn pop_best(&mut self) -> Option<(D, T)> {
+        let best = self.occupied.first_from(0)?;
+        Some(self.pop_from(best, End::Best))
+    }
+
+    /// Removes the worst element.
+    ///
+    /// Since the heap is no longer at the cap afterwards, `worst` goes back to max until the cap is reached again.
+    /// If ties are kept, the heap can still be at the cap, in which case `worst` stays the `cap`-th best distance.
+    pub fn pop_worst(&mut self) -> Option<(D, T)> {
+        let worst = self.occupied.last_to(self.end())?;
+        Some(self.pop_from(worst, End::Worst))
+    }
+
+    /// Removes every element in best-to-worse order, the same order that `pop_best` would remove them in.
+    ///
+    /// Any elements the iterator doesn't yield are dropped along with it.
+    pub fn drain_sorted(&mut self) -> DrainSorted<'_, T, D, B> {
+        DrainSorted { heap: self }
+    }
+
+    /// Consumes the heap and returns every element in best-to-worse order.
+    ///
+    /// ```
+    /// use hamming_heap::FixedHammingHeap;
+    /// let mut candidates = FixedHammingHeap::new_distances(129);
+    /// candidates.set_capacity(2);
+    /// candidates.push(5, 'a');
+    /// candidates.push(9, 'b');
+    /// candidates.push(1, 'c');
+    /// assert_eq!(candidates.into_sorted_vec(), [(1, 'c'), (5, 'a')]);
+    /// ```
+    pub fn into_sorted_vec(mut self) -> Vec<(D, T)> {
+        self.drain_sorted().collect()
+    }
+
+    /// Removes an element from the occupied bucket `distance` at the `end` of the heap.
+    fn pop_from(&mut self, distance: usize, end: End) -> (D, T) {
+        let item = self
+            .tie_break
+            .take(&mut self.distances, distance, end)
+            .expect("occupied bucket was empty");
+        if self.distances.bucket(distance).is_empty() {
+            self.occupied.remove(distance);
         }
+        self.size -= 1;
+        self.reset_worst();
+        (distance::from_index(distance), item)
     }
 
     /// Fill a slice with the `top` elements and return the part of the slice written.
+    ///
+    /// The elements are written in best-to-worse order following `iter`, so the first `strict_len` elements
+    /// are strictly better than the `tie_group` and the rest of the written part belongs to it.
     pub fn fill_slice<'a>(&self, s: &'a mut [T]) -> &'a mut [T]
     where
         T: Clone,
     {
         let total_fill = std::cmp::min(s.len(), self.size);
-        for (ix, f) in self.distances[..=self.end()]
-            .iter()
-            .flat_map(|v| v.iter())
-            .take(total_fill)
-            .enumerate()
-        {
-            s[ix] = f.clone();
+        for (slot, (_, item)) in s.iter_mut().zip(self.iter()) {
+            *slot = item.clone();
         }
         &mut s[0..total_fill]
     }
 
+    /// Fill a slice with the `top` elements along with their distances and return the part of the slice written.
+    ///
+    /// The elements are written in the order of `iter`.
+    pub fn fill_pairs<'a>(&self, s: &'a mut [(D, T)]) -> &'a mut [(D, T)]
+    where
+        T: Clone,
+    {
+        let total_fill = std::cmp::min(s.len(), self.size);
+        for (slot, (distance, item)) in s.iter_mut().zip(self.iter()) {
+            *slot = (distance, item.clone());
+        }
+        &mut s[0..total_fill]
+    }
+
+    /// Fill `distances` and `items` with the distances and `top` elements respectively.
+    ///
+    /// The elements are written in the order of `iter`. Returns the number of elements written, which is
+    /// limited by the shorter slice.
+    pub fn fill_distances(&self, distances: &mut [D], items: &mut [T]) -> usize
+    where
+        T: Clone,
+    {
+        let total_fill = std::cmp::min(std::cmp::min(distances.len(), items.len()), self.size);
+        for ((distance_slot, item_slot), (distance, item)) in
+            distances.iter_mut().zip(items.iter_mut()).zip(self.iter())
+        {
+            *distance_slot = distance;
+            *item_slot = item.clone();
+        }
+        total_fill
+    }
+
+    /// Moves every element to the end of `out` in the order of `iter` without cloning them.
+    ///
+    /// This is the order `fill_pairs` writes. `drain_sorted` instead removes tied elements in the order the
+    /// tie-breaking policy picks them, which is the reverse for the default `Lifo` policy. The heap is empty
+    /// afterwards but keeps its capacity and allocated memory.
+    pub fn drain_into(&mut self, out: &mut Vec<(D, T)>) {
+        if self.is_empty() {
+            return;
+        }
+        out.reserve(self.size);
+        for distance in self.occupied.iter() {
+            let start = out.len();
+            let tied = distance::from_index(distance);
+            while let Some(item) = self.distances.pop(distance) {
+                out.push((tied, item));
+            }
+            out[start..].reverse();
+        }
+        self.occupied.clear();
+        self.size = 0;
+        self.worst = self.distances.num_buckets() - 1;
+    }
+
+    /// Sets the policy that decides which of the elements tied at a distance is evicted or popped first.
+    ///
+    /// The policy can be changed at any time. Switching to an `Order` policy sorts the elements at every distance.
+    pub fn set_tie_break(&mut self, tie_break: TieBreak<T>) {
+        tie_break.sort(&mut self.distances);
+        self.tie_break = tie_break;
+    }
+
+    /// Gets the policy that decides which of the elements tied at a distance is evicted or popped first.
+    pub fn tie_break(&self) -> TieBreak<T> {
+        self.tie_break
+    }
+
     /// Gets the worst distance in the queue currently.
     ///
     /// This is initialized to max (which is the worst possible distance) until `cap` elements have been inserted.
-    pub fn worst(&self) -> u32 {
-        self.worst
+    /// If ties are kept, this is the `cap`-th best distance once the heap is at the cap.
+    pub fn worst(&self) -> D {
+        distance::from_index(self.worst)
     }
 
     /// Returns true if the cap has been reached.
+    ///
+    /// If ties are kept, the length can be above the cap while this is true.
     pub fn at_cap(&self) -> bool {
-        self.size == self.cap
+        self.size >= self.cap
+    }
+
+    /// Gets the `cap`-th best distance and every element at it once the heap is at the cap.
+    ///
+    /// If ties are kept, this is every item tied with the `cap`-th best item. Otherwise, this is only the tied items
+    /// that fit within the cap. Returns `None` if the heap is not at the cap.
+    pub fn tie_group(&self) -> Option<(D, &[T])> {
+        if self.at_cap() && !self.is_empty() {
+            Some((
+                distance::from_index(self.worst),
+                self.distances.bucket(self.worst),
+            ))
+        } else {
+            None
+        }
+    }
+
+    /// Gets the number of elements strictly better than the `tie_group`.
+    ///
+    /// This is the length of the heap if it is not at the cap.
+    pub fn strict_len(&self) -> usize {
+        match self.tie_group() {
+            Some((_, ties)) => self.size - ties.len(),
+            None => self.size,
+        }
+    }
+
+    /// Iterate over the elements strictly better than the `tie_group` in best-to-worse order.
+    ///
+    /// This iterates over every element if the heap is not at the cap.
+    pub fn iter_strict(&self) -> Iter<'_, T, D, B> {
+        let end = if self.tie_group().is_some() {
+            self.worst
+        } else {
+            self.distances.num_buckets()
+        };
+        Iter::new(&self.distances, &self.occupied, 0..end, self.strict_len())
     }
 
     /// Iterate over the entire queue in best-to-worse order.
-    pub fn iter(&mut self) -> impl Iterator<Item = (u32, &T)> {
-        self.distances[..=self.end()]
-            .iter()
-            .enumerate()
-            .flat_map(|(distance, v)| v.iter().map(move |item| (distance as u32, item)))
+    ///
+    /// With an `Order` policy, every distance is kept sorted, so this yields the elements in full lexicographic
+    /// `(distance, item)` order, which is the order `drain_sorted` removes them in. With any other policy, tied
+    /// elements are yielded in the order they were added.
+    pub fn iter(&self) -> Iter<'_, T, D, B> {
+        Iter::new(
+            &self.distances,
+            &self.occupied,
+            0..self.end() + 1,
+            self.size,
+        )
     }
 
     /// Iterate over the entire queue in best-to-worse order.
-    pub fn iter_mut(&mut self) -> impl Iterator<Item = (u32, &mut T)> {
-        let end = self.end();
-        self.distances[..=end]
-            .iter_mut()
-            .enumerate()
-            .flat_map(|(distance, v)| v.iter_mut().map(move |item| (distance as u32, item)))
+    pub fn iter_mut(&mut self) -> IterMut<'_, T, D, B> {
+        let range = 0..self.end() + 1;
+        IterMut::new(&mut self.distances, range, self.size)
+    }
+
+    /// Iterate over the elements with a distance in `range` in best-to-worse order.
+    pub fn iter_range<R>(&self, range: R) -> Iter<'_, T, D, B>
+    where
+        R: RangeBounds<D>,
+    {
+        let range = distance::index_range(&range, self.end() + 1);
+        Iter::counted(&self.distances, &self.occupied, range)
+    }
+
+    /// Iterate over the distances that have elements in best-to-worse order, along with their elements.
+    pub fn buckets(&self) -> BucketsIter<'_, T, D, B> {
+        BucketsIter::new(&self.distances, &self.occupied, 0..self.end() + 1)
+    }
+
+    /// Gets the elements at `distance`.
+    ///
+    /// The slice is empty if `distance` is outside of the configured distances.
+    pub fn bucket(&self, distance: D) -> &[T] {
+        let distance = distance.to_index();
+        if distance < self.distances.num_buckets() {
+            self.distances.bucket(distance)
+        } else {
+            &[]
+        }
+    }
+
+    /// Gets the elements at `distance` mutably.
+    ///
+    /// The slice is empty if `distance` is outside of the configured distances.
+    pub fn bucket_mut(&mut self, distance: D) -> &mut [T] {
+        let distance = distance.to_index();
+        if distance < self.distances.num_buckets() {
+            self.distances.bucket_mut(distance)
+        } else {
+            &mut []
+        }
     }
 
     /// Add a feature to the search with the precondition we are already at the cap.
     ///
-    /// Warning: This function cannot cause undefined behavior, but it can be used incorrectly.
-    /// This should only be called after `at_cap()` can been called and returns true.
     /// This shouldn't be used unless you profile and actually find that the branch predictor is having
-    /// issues with the if statement in `push()`.
-    pub unsafe fn push_at_cap(&mut self, distance: u32, item: T) -> bool {
-        // We stop searching once we have enough features under the search distance,
-        // so if this is true it will always get added to the FeatureHeap.
+    /// issues with the if statement in `push()`. Prefer `push_with` or `would_accept` when the cost is in building
+    /// the item.
+    ///
+    /// # Safety
+    ///
+    /// This function cannot cause undefined behavior, but it can be used incorrectly.
+    /// This should only be called after `at_cap()` can been called and returns true.
+    pub unsafe fn push_at_cap(&mut self, distance: D, item: T) -> bool {
+        self.push_index_at_cap_added(distance.to_index(), item)
+    }
+
+    /// Add a feature at the bucket index `distance` with the precondition we are below the cap.
+    #[inline]
+    fn push_index_below_cap(&mut self, distance: usize, item: T) {
+        self.tie_break.insert(&mut self.distances, distance, item);
+        self.occupied.insert(distance);
+        self.size += 1;
+        // Set the worst feature appropriately.
+        if self.size == self.cap {
+            self.update_worst();
+        }
+    }
+
+    /// Add a feature at the bucket index `distance` with the precondition we are already at the cap, returning true
+    /// if it was added.
+    ///
+    /// The default `Lifo` policy without kept ties drops the evicted item straight away, so only the other policies
+    /// go through `push_index_at_cap`.
+    #[inline]
+    fn push_index_at_cap_added(&mut self, distance: usize, item: T) -> bool {
+        if self.keep_ties || !matches!(self.tie_break, TieBreak::Lifo) {
+            return !matches!(
+                self.push_index_at_cap(distance, item),
+                PushResult::Rejected(_)
+            );
+        }
         if distance < self.worst {
-            self.distances[distance as usize].push(item);
-            self.remove_worst();
+            self.distances.push(distance, item);
+            self.occupied.insert(distance);
+            let worst = self.worst;
+            self.distances.pop(worst);
+            if self.distances.bucket(worst).is_empty() {
+                self.occupied.remove(worst);
+            }
+            self.update_worst();
             true
         } else {
             false
         }
     }
 
+    /// Add a feature at the bucket index `distance` with the precondition we are already at the cap.
+    fn push_index_at_cap(&mut self, distance: usize, item: T) -> PushResult<T, D> {
+        if self.keep_ties {
+            return self.push_index_keeping_ties(distance, item);
+        }
+        // We stop searching once we have enough features under the search distance,
+        // so if this is true it will always get added to the FeatureHeap.
+        if distance < self.worst || self.beats_worst_tie(distance, &item) {
+            self.tie_break.insert(&mut self.distances, distance, item);
+            self.occupied.insert(distance);
+            let (worst, evicted) = self.remove_worst();
+            PushResult::Evicted(distance::from_index(worst), evicted)
+        } else {
+            PushResult::Rejected(item)
+        }
+    }
+
+    /// Add a feature at the bucket index `distance` at the cap while keeping every item tied with the worst.
+    fn push_index_keeping_ties(&mut self, distance: usize, item: T) -> PushResult<T, D> {
+        if distance > self.worst {
+            return PushResult::Rejected(item);
+        }
+        self.tie_break.insert(&mut self.distances, distance, item);
+        self.occupied.insert(distance);
+        self.size += 1;
+        let worst = self.worst;
+        let ties = self.distances.bucket(worst).len();
+        // A better item can only ever push one group of ties out.
+        if distance < worst && self.size - ties >= self.cap {
+            let mut evicted = Vec::with_capacity(ties);
+            while let Some(item) = self.distances.pop(worst) {
+                evicted.push(item);
+            }
+            evicted.reverse();
+            self.occupied.remove(worst);
+            self.size -= ties;
+            self.update_worst();
+            PushResult::EvictedTies(distance::from_index(worst), evicted)
+        } else {
+            PushResult::Added
+        }
+    }
+
+    /// Checks if `item` at the worst bucket index `distance` orders before one of the items tied there.
+    ///
+    /// This is only ever true with an `Order` tie-breaking policy, and only compares against the last item in the
+    /// sorted worst bucket.
+    fn beats_worst_tie(&self, distance: usize, item: &T) -> bool {
+        match self.tie_break {
+            TieBreak::Order(cmp) if distance == self.worst => self
+                .distances
+                .bucket(distance)
+                .last()
+                .is_some_and(|tied| cmp(item, tied) == Ordering::Less),
+            _ => false,
+        }
+    }
+
+    /// Checks if a feature at the bucket index `distance` could be kept, given that the capacity was set.
+    fn accepts_index(&self, distance: usize) -> bool {
+        let orders_ties = self.keep_ties || matches!(self.tie_break, TieBreak::Order(_));
+        !self.at_cap() || distance < self.worst || (orders_ties && distance == self.worst)
+    }
+
+    /// Sets the worst back to max, or to the worst item if the heap is still at the cap.
+    fn reset_worst(&mut self) {
+        self.worst = self.distances.num_buckets() - 1;
+        if self.at_cap() {
+            self.update_worst();
+        }
+    }
+
+    /// Drops the worst distances while they have `cap` items strictly better than them and ties are kept.
+    fn drop_excess_ties(&mut self) {
+        while self.keep_ties && self.at_cap() && !self.is_empty() {
+            let ties = self.distances.bucket(self.worst).len();
+            if self.size - ties < self.cap {
+                break;
+            }
+            self.distances.clear(self.worst);
+            self.occupied.remove(self.worst);
+            self.size -= ties;
+            self.update_worst();
+        }
+    }
+
     /// Gets the smallest known inclusive end of the datastructure.
     fn end(&self) -> usize {
         if self.at_cap() {
-            self.worst as usize
+            self.worst
         } else {
-            self.distances.len() - 1
+            self.distances.num_buckets() - 1
         }
     }
 
     /// Updates the worst when it has been set.
     fn update_worst(&mut self) {
         // If there is nothing left, it gets reset to max.
-        self.worst = self.distances[0..=self.worst as usize]
-            .iter()
-            .rev()
-            .position(|v| !v.is_empty())
-            .map(|n| self.worst - n as u32)
-            .unwrap_or(self.distances.len() as u32 - 1);
+        self.worst = self
+            .occupied
+            .last_to(self.worst)
+            .unwrap_or(self.distances.num_buckets() - 1);
     }
 
     /// Remove the worst item and update the worst distance.
-    fn remove_worst(&mut self) {
-        self.distances[self.worst as usize].pop();
+    fn remove_worst(&mut self) -> (usize, T) {
+        let worst = self.worst;
+        let item = self.evict(worst);
+        if self.distances.bucket(worst).is_empty() {
+            self.occupied.remove(worst);
+        }
         self.update_worst();
+        (worst, item)
+    }
+
+    /// Removes the item that the tie-breaking policy picks from the worst end of the bucket `distance`.
+    ///
+    /// This leaves the size, occupancy and worst distance to the caller.
+    fn evict(&mut self, distance: usize) -> T {
+        self.tie_break
+            .take(&mut self.distances, distance, End::Worst)
+            .expect("evicted from an empty bucket")
     }
 }
 
-impl<T> Default for FixedHammingHeap<T> {
+/// Reports a `push` that was given a distance or capacity it can't handle, out of line so `push` stays small.
+#[cold]
+#[inline(never)]
+fn push_failed(error: HammingHeapError) -> ! {
+    panic!("{}", error)
+}
+
+/// The outcome of [`FixedHammingHeap::push_evict`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PushResult<T, D = u32> {
+    /// The item was added without displacing anything because the heap was below its capacity.
+    Added,
+    /// The item was not good enough to be added and is handed back.
+    Rejected(T),
+    /// The item was added and displaced the worst item, which is handed back along with its distance.
+    Evicted(D, T),
+    /// The item was added while ties are kept and displaced every item tied at the worst distance, which are handed
+    /// back in bucket order (push order for `Lifo` and `Fifo`, sorted order for `Order`) along with their distance.
+    EvictedTies(D, Vec<T>),
+}
+
+/// Removes the elements of a [`FixedHammingHeap`] in best-to-worse order.
+///
+/// This is created by [`FixedHammingHeap::drain_sorted`].
+pub struct DrainSorted<'a, T, D, B>
+where
+    D: Distance,
+    B: Buckets<T>,
+{
+    heap: &'a mut FixedHammingHeap<T, D, B>,
+}
+
+impl<T, D, B> Iterator for DrainSorted<'_, T, D, B>
+where
+    D: Distance,
+    B: Buckets<T>,
+{
+    type Item = (D, T);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.heap.pop_best()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.heap.len(), Some(self.heap.len()))
+    }
+}
+
+impl<T, D, B> DoubleEndedIterator for DrainSorted<'_, T, D, B>
+where
+    D: Distance,
+    B: Buckets<T>,
+{
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.heap.pop_worst()
+    }
+}
+
+impl<T, D, B> ExactSizeIterator for DrainSorted<'_, T, D, B>
+where
+    D: Distance,
+    B: Buckets<T>,
+{
+}
+
+impl<T, D, B> Drop for DrainSorted<'_, T, D, B>
+where
+    D: Distance,
+    B: Buckets<T>,
+{
+    fn drop(&mut self) {
+        if !self.heap.is_empty() {
+            self.heap.clear();
+        }
+    }
+}
+
+impl<T, D, B> Default for FixedHammingHeap<T, D, B>
+where
+    D: Distance,
+    B: Buckets<T>,
+{
     fn default() -> Self {
         Self {
             cap: 0,
             size: 0,
             worst: 0,
-            distances: vec![],
+            distances: B::default(),
+            occupied: Occupancy::default(),
+            tie_break: TieBreak::default(),
+            keep_ties: false,
+            _marker: PhantomData,
         }
     }
 }
@@ -268,3 +1092,459 @@ fn test_fixed_heap() {
     arr[1..3].sort_unstable();
     assert_eq!(arr, [10, 5, 11]);
 }
+
+#[cfg(test)]
+#[test]
+fn test_fixed_heap_flat() {
+    use crate::FlatBuckets;
+    let mut candidates: FixedHammingHeap<u32, u32, FlatBuckets<u32>> = FixedHammingHeap::default();
+    candidates.set_distances(11);
+    candidates.set_capacity(3);
+    for (ix, &distance) in [5, 4, 3, 6, 7, 2, 3, 10, 6, 4, 1, 2].iter().enumerate() {
+        candidates.push(distance, ix as u32);
+    }
+    let mut arr = [0; 3];
+    candidates.fill_slice(&mut arr);
+    arr[1..3].sort_unstable();
+    assert_eq!(arr, [10, 5, 11]);
+}
+
+#[cfg(test)]
+#[test]
+fn test_truncate_beyond() {
+    let mut candidates: FixedHammingHeap<u32> = FixedHammingHeap::new_distances(11);
+    candidates.set_capacity(4);
+    for (ix, &distance) in [7, 2, 5, 9].iter().enumerate() {
+        candidates.push(distance, ix as u32);
+    }
+    assert_eq!(candidates.worst(), 9);
+    assert_eq!(
+        candidates.iter_range(3..=7).collect::<Vec<_>>(),
+        [(5, &2), (7, &0)]
+    );
+    candidates.truncate_beyond(5);
+    assert_eq!(candidates.len(), 2);
+    assert_eq!(candidates.worst(), 10);
+    assert!(candidates.push(8, 4));
+    assert!(candidates.push(3, 5));
+    assert_eq!(candidates.worst(), 8);
+    assert!(!candidates.push(9, 6));
+}
+
+#[cfg(test)]
+#[test]
+fn test_pop_both_ends() {
+    let mut candidates: FixedHammingHeap<u32> = FixedHammingHeap::new_distances(11);
+    candidates.set_capacity(4);
+    for (ix, &distance) in [7, 2, 5, 9, 3].iter().enumerate() {
+        candidates.push(distance, ix as u32);
+    }
+    assert_eq!(candidates.worst(), 7);
+    assert_eq!(candidates.pop_worst(), Some((7, 0)));
+    assert_eq!(candidates.worst(), 10);
+    assert!(!candidates.at_cap());
+    assert_eq!(candidates.pop_best(), Some((2, 1)));
+    assert_eq!(candidates.len(), 2);
+    assert!(candidates.push(8, 5));
+    assert!(candidates.push(6, 6));
+    assert_eq!(candidates.worst(), 8);
+    {
+        let mut drain = candidates.drain_sorted();
+        assert_eq!(drain.len(), 4);
+        assert_eq!(drain.next(), Some((3, 4)));
+        assert_eq!(drain.next_back(), Some((8, 5)));
+    }
+    assert!(candidates.is_empty());
+    assert!(candidates.push(10, 7));
+    assert_eq!(candidates.into_sorted_vec(), [(10, 7)]);
+}
+
+#[cfg(test)]
+#[test]
+fn test_truncation_against_reference() {
+    use crate::testing::{insert_sorted, XorShift};
+
+    let mut rng = XorShift::new(0x853c_49e6_748f_ea9b);
+    let distances = 17;
+    let mut candidates: FixedHammingHeap<u32> = FixedHammingHeap::new_distances(distances);
+    let mut cap = 8;
+    candidates.set_capacity(cap);
+    // The reference holds the distances that should be in the heap.
+    let mut reference: Vec<u32> = vec![];
+    for step in 0..5000u32 {
+        match rng.below(10) {
+            0 => {
+                cap = 1 + rng.below(12) as usize;
+                candidates.set_capacity(cap);
+                reference.truncate(cap);
+            }
+            1 => {
+                let len = rng.below(12) as usize;
+                candidates.set_len(len);
+                reference.truncate(len);
+            }
+            2 => {
+                let len = rng.below(12) as usize;
+                let removed = candidates.truncate_returning(len);
+                let expected: Vec<u32> = reference
+                    .iter()
+                    .rev()
+                    .take(removed.len())
+                    .cloned()
+                    .collect();
+                assert_eq!(
+                    removed.iter().map(|&(d, _)| d).collect::<Vec<_>>(),
+                    expected
+                );
+                reference.truncate(len);
+            }
+            _ => {
+                let distance = rng.below(distances as u64) as u32;
+                let accepted = reference.len() < cap || distance < *reference.last().unwrap();
+                assert_eq!(candidates.push(distance, step), accepted);
+                if accepted {
+                    if reference.len() == cap {
+                        reference.pop();
+                    }
+                    insert_sorted(&mut reference, distance);
+                }
+            }
+        }
+        assert_eq!(candidates.len(), reference.len());
+        assert_eq!(
+            candidates.iter().map(|(d, _)| d).collect::<Vec<_>>(),
+            reference
+        );
+        if candidates.at_cap() {
+            assert_eq!(candidates.worst(), *reference.last().unwrap());
+        } else {
+            assert_eq!(candidates.worst(), distances as u32 - 1);
+        }
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn test_push_evict() {
+    let mut candidates: FixedHammingHeap<Vec<u8>> = FixedHammingHeap::new_distances(11);
+    candidates.set_capacity(2);
+    assert_eq!(candidates.push_evict(6, vec![6]), PushResult::Added);
+    assert_eq!(candidates.push_evict(4, vec![4]), PushResult::Added);
+    // Ties with the worst distance are rejected, just like with `push`.
+    assert_eq!(
+        candidates.push_evict(6, vec![7]),
+        PushResult::Rejected(vec![7])
+    );
+    assert_eq!(
+        candidates.push_evict(1, vec![1]),
+        PushResult::Evicted(6, vec![6])
+    );
+    assert_eq!(candidates.worst(), 4);
+    assert_eq!(
+        candidates.push_evict(1, vec![2]),
+        PushResult::Evicted(4, vec![4])
+    );
+    assert_eq!(candidates.into_sorted_vec(), [(1, vec![2]), (1, vec![1])]);
+
+    // Ordered ties are handed back sorted rather than in push order.
+    let mut candidates: FixedHammingHeap<u32> = FixedHammingHeap::new_distances(11);
+    candidates.set_capacity(1);
+    candidates.set_keep_ties(true);
+    candidates.set_tie_break(TieBreak::Order(|a, b| a.cmp(b)));
+    for &item in &[9, 2, 5] {
+        assert_eq!(candidates.push_evict(6, item), PushResult::Added);
+    }
+    assert_eq!(
+        candidates.push_evict(1, 0),
+        PushResult::EvictedTies(6, vec![2, 5, 9])
+    );
+}
+
+#[cfg(test)]
+#[test]
+fn test_push_with() {
+    let mut candidates: FixedHammingHeap<u32> = FixedHammingHeap::new_distances(11);
+    assert!(!candidates.would_accept(3));
+    candidates.set_capacity(2);
+    let mut built = 0;
+    for &distance in &[10, 10, 9, 10, 4, 9, 7] {
+        let accepted = candidates.would_accept(distance);
+        let added = candidates.push_with(distance, || {
+            built += 1;
+            distance
+        });
+        assert_eq!(accepted, added);
+    }
+    // Only the pushes of 10, 10, 9, 4 and 7 build an item.
+    assert_eq!(built, 5);
+    assert!(!candidates.would_accept(11));
+    assert_eq!(candidates.into_sorted_vec(), [(4, 4), (7, 7)]);
+
+    let mut candidates: FixedHammingHeap<u32> = FixedHammingHeap::new_distances(11);
+    assert_eq!(
+        candidates.try_push_with(3, || unreachable!()),
+        Err(HammingHeapError::ZeroCapacity)
+    );
+
+    // With `Order`, an item tied at the worst distance is built before it can be rejected.
+    let mut candidates: FixedHammingHeap<u32> = FixedHammingHeap::new_distances(11);
+    candidates.set_capacity(1);
+    candidates.set_tie_break(TieBreak::Order(|a, b| a.cmp(b)));
+    assert!(candidates.push_with(5, || 5));
+    let mut built = 0;
+    assert!(!candidates.push_with(5, || {
+        built += 1;
+        6
+    }));
+    assert_eq!(built, 1);
+    assert!(candidates.push_with(5, || 4));
+    assert_eq!(candidates.into_sorted_vec(), [(5, 4)]);
+}
+
+#[cfg(test)]
+#[test]
+fn test_fill_variants() {
+    let mut candidates: FixedHammingHeap<char> = FixedHammingHeap::new_distances(11);
+    candidates.set_capacity(3);
+    for &(distance, item) in &[(8, 'a'), (2, 'b'), (5, 'c'), (9, 'd')] {
+        candidates.push(distance, item);
+    }
+    let mut pairs = [(0, ' '); 4];
+    assert_eq!(
+        candidates.fill_pairs(&mut pairs),
+        [(2, 'b'), (5, 'c'), (8, 'a')]
+    );
+    let mut distances = [0; 2];
+    let mut items = [' '; 5];
+    assert_eq!(candidates.fill_distances(&mut distances, &mut items), 2);
+    assert_eq!(distances, [2, 5]);
+    assert_eq!(&items[..2], ['b', 'c']);
+
+    let mut out = vec![(0, 'z')];
+    candidates.drain_into(&mut out);
+    assert_eq!(out, [(0, 'z'), (2, 'b'), (5, 'c'), (8, 'a')]);
+    assert!(candidates.is_empty());
+    assert!(candidates.push(10, 'e'));
+
+    // Tied elements are drained in the order they are filled.
+    for &(distance, item) in &[(3, 'x'), (3, 'y'), (1, 'w')] {
+        candidates.push(distance, item);
+    }
+    let mut pairs = [(0, ' '); 3];
+    candidates.fill_pairs(&mut pairs);
+    out.clear();
+    candidates.drain_into(&mut out);
+    assert_eq!(out, pairs);
+    assert_eq!(out, [(1, 'w'), (3, 'x'), (3, 'y')]);
+}
+
+#[cfg(test)]
+#[test]
+fn test_keep_ties_against_reference() {
+    use crate::testing::{insert_sorted, XorShift};
+
+    let mut rng = XorShift::new(0x2545_f491_4f6c_dd1d);
+    let mut candidates: FixedHammingHeap<u32> = FixedHammingHeap::new_distances(9);
+    let mut cap = 4;
+    candidates.set_capacity(cap);
+    candidates.set_keep_ties(true);
+    // The reference holds the sorted distances that should be in the heap.
+    let mut reference: Vec<u32> = vec![];
+    let keep_ties = |reference: &mut Vec<u32>, cap: usize| {
+        if reference.len() >= cap {
+            let kth = reference[cap - 1];
+            reference.retain(|&distance| distance <= kth);
+        }
+    };
+    for _ in 0..5000 {
+        match rng.below(12) {
+            0 => {
+                cap = 1 + rng.below(6) as usize;
+                candidates.set_capacity(cap);
+                keep_ties(&mut reference, cap);
+            }
+            1 => {
+                assert_eq!(
+                    candidates.pop_best().map(|(d, _)| d),
+                    reference.first().copied()
+                );
+                if !reference.is_empty() {
+                    reference.remove(0);
+                }
+            }
+            2 => {
+                assert_eq!(candidates.pop_worst().map(|(d, _)| d), reference.pop());
+            }
+            _ => {
+                let distance = rng.below(9) as u32;
+                let accepted = reference.len() < cap || distance <= reference[cap - 1];
+                assert_eq!(candidates.would_accept(distance), accepted);
+                let result = candidates.push_evict(distance, distance);
+                if accepted {
+                    insert_sorted(&mut reference, distance);
+                    let before = reference.len();
+                    keep_ties(&mut reference, cap);
+                    match result {
+                        PushResult::Added => assert_eq!(reference.len(), before),
+                        PushResult::EvictedTies(worst, ties) => {
+                            assert_eq!(before - reference.len(), ties.len());
+                            assert!(ties.iter().all(|&item| item == worst));
+                        }
+                        _ => panic!("unexpected push result"),
+                    }
+                } else {
+                    assert_eq!(result, PushResult::Rejected(distance));
+                }
+            }
+        }
+        let distances: Vec<u32> = candidates.iter().map(|(d, _)| d).collect();
+        assert_eq!(distances, reference);
+        assert_eq!(candidates.at_cap(), reference.len() >= cap);
+        if reference.len() >= cap {
+            let kth = reference[cap - 1];
+            assert_eq!(candidates.worst(), kth);
+            let strict = reference.iter().filter(|&&d| d < kth).count();
+            assert_eq!(candidates.strict_len(), strict);
+            assert_eq!(candidates.iter_strict().count(), strict);
+            assert_eq!(
+                candidates.tie_group().map(|(d, ties)| (d, ties.len())),
+                Some((kth, reference.len() - strict))
+            );
+        } else {
+            assert_eq!(candidates.tie_group(), None);
+            assert_eq!(candidates.strict_len(), reference.len());
+        }
+    }
+    candidates.set_keep_ties(false);
+    assert!(candidates.len() <= cap);
+}
+
+#[cfg(test)]
+#[test]
+fn test_secondary_key_against_reference() {
+    use crate::testing::XorShift;
+
+    let mut rng = XorShift::new(0x9e37_79b9_7f4a_7c15);
+    let mut candidates: FixedHammingHeap<(f64, u64)> = FixedHammingHeap::new_distances(6);
+    candidates.set_capacity(5);
+    candidates.set_tie_break(TieBreak::by_key());
+    let mut reference: Vec<(u32, f64, u64)> = vec![];
+    for id in 0..2000 {
+        let distance = rng.below(6) as u32;
+        let key = if rng.below(50) == 0 {
+            f64::NAN
+        } else {
+            rng.below(1000) as f64 / 8.0
+        };
+        candidates.push(distance, (key, id));
+        reference.push((distance, key, id));
+        reference.sort_by(|a, b| {
+            (a.0, a.1.is_nan())
+                .cmp(&(b.0, b.1.is_nan()))
+                .then(a.1.partial_cmp(&b.1).unwrap_or(Ordering::Equal))
+        });
+        reference.truncate(5);
+        if id % 100 == 0 {
+            let mut pairs = vec![(0, (0.0, 0)); 5];
+            let filled = candidates.fill_pairs(&mut pairs);
+            let keys: Vec<_> = filled.iter().map(|&(d, (key, _))| (d, key)).collect();
+            let expected: Vec<_> = reference.iter().map(|&(d, key, _)| (d, key)).collect();
+            assert_eq!(format!("{:?}", keys), format!("{:?}", expected));
+        }
+    }
+    let drained: Vec<_> = candidates
+        .into_sorted_vec()
+        .into_iter()
+        .map(|(d, (key, _))| (d, key))
+        .collect();
+    let expected: Vec<_> = reference.iter().map(|&(d, key, _)| (d, key)).collect();
+    assert_eq!(format!("{:?}", drained), format!("{:?}", expected));
+}
+
+#[cfg(test)]
+#[test]
+fn test_for_descriptor() {
+    let mut candidates: FixedHammingHeap<usize, u8> = FixedHammingHeap::default();
+    assert_eq!(
+        candidates.try_set_descriptor::<[u8; 32]>(),
+        Err(HammingHeapError::TooManyDistances { distances: 257 })
+    );
+    let mut candidates: FixedHammingHeap<usize, u16> = FixedHammingHeap::default();
+    candidates.set_descriptor::<[u8; 32]>();
+    candidates.set_capacity(2);
+    let query = [0x55u8; 32];
+    let mut features = [[0x55u8; 32]; 4];
+    features[0][0] = 0xaa;
+    features[1] = [0xaa; 32];
+    features[3][31] = 0x54;
+    for (ix, feature) in features.iter().enumerate() {
+        candidates.push_descriptor(&query, feature, ix);
+    }
+    assert!(candidates
+        .try_push_descriptor(&query[..], &features[1][..], 4)
+        .is_ok());
+    assert_eq!(candidates.into_sorted_vec(), [(0, 2), (1, 3)]);
+
+    let mut candidates: FixedHammingHeap<(), u8> = FixedHammingHeap::default();
+    candidates.set_distances(9);
+    candidates.set_capacity(1);
+    assert_eq!(
+        candidates.try_push_descriptor(&0u16, &!0u16, ()),
+        Err(HammingHeapError::DistanceOutOfRange {
+            distance: 16,
+            distances: 9
+        })
+    );
+}
+
+#[cfg(test)]
+#[test]
+fn test_push_descriptor_bounded_against_full() {
+    use crate::testing::XorShift;
+
+    let mut rng = XorShift::new(0x2545_f491_4f6c_dd1d);
+    let query = [
+        rng.next_u64(),
+        rng.next_u64(),
+        rng.next_u64(),
+        rng.next_u64(),
+    ];
+    // Bias features towards the query so that the candidates get close and the bound tightens.
+    let features: Vec<[u64; 4]> = (0..500)
+        .map(|_| {
+            let mut feature = query;
+            for word in &mut feature {
+                *word ^= rng.next_u64() & rng.next_u64() & rng.next_u64();
+            }
+            feature
+        })
+        .collect();
+    for &(keep_ties, order) in &[(false, false), (true, false), (false, true)] {
+        let mut full: FixedHammingHeap<usize> = FixedHammingHeap::for_descriptor::<[u64; 4]>();
+        let mut bounded: FixedHammingHeap<usize> = FixedHammingHeap::for_descriptor::<[u64; 4]>();
+        for heap in [&mut full, &mut bounded].iter_mut() {
+            heap.set_capacity(10);
+            heap.set_keep_ties(keep_ties);
+            // Later features order after the ones kept so far, so a tie at the worst distance is rejected.
+            if order {
+                heap.set_tie_break(TieBreak::Order(|a, b| a.cmp(b)));
+            }
+        }
+        let mut skipped = 0;
+        for (ix, feature) in features.iter().enumerate() {
+            let added = full.push_descriptor(&query, feature, ix);
+            match bounded.push_descriptor_bounded(&query, feature, ix) {
+                (BoundedDistance::Within(distance), bounded_added) => {
+                    assert_eq!(distance, query.hamming(feature));
+                    assert_eq!(bounded_added, added);
+                }
+                (BoundedDistance::Abandoned { skipped_words }, bounded_added) => {
+                    assert!(!added && !bounded_added);
+                    skipped += skipped_words;
+                }
+            }
+        }
+        assert!(skipped > 0);
+        assert_eq!(full.into_sorted_vec(), bounded.into_sorted_vec());
+    }
+}
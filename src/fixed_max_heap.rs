@@ -0,0 +1,333 @@
+use crate::buckets::{Buckets, VecBuckets};
+use crate::distance::{self, Distance};
+use crate::fixed_heap::{FixedHammingHeap, PushResult};
+use crate::tie_break::TieBreak;
+use crate::HammingHeapError;
+use core::iter::FusedIterator;
+
+/// This keeps the farthest `cap` items at all times.
+///
+/// This is the counterpart of [`FixedHammingHeap`] for hard-negative mining and outlier detection, where the items
+/// with the largest distances are the interesting ones. It stores every distance mirrored within the configured
+/// distances in a regular `FixedHammingHeap`, so `worst` is the smallest distance that is kept and items at or
+/// below it are rejected once the cap is reached.
+///
+/// ```
+/// use hamming_heap::FixedHammingMaxHeap;
+/// let mut negatives = FixedHammingMaxHeap::new_distances(129);
+/// negatives.set_capacity(2);
+/// let query = 0u128;
+/// for (node, &feature) in [0b1u128, 0b1111, 0b11, 0b111].iter().enumerate() {
+///     negatives.push((query ^ feature).count_ones(), node);
+/// }
+/// assert_eq!(negatives.worst(), 3);
+/// assert_eq!(negatives.iter().collect::<Vec<_>>(), [(4, &1), (3, &3)]);
+/// ```
+#[derive(Clone, Debug)]
+pub struct FixedHammingMaxHeap<T, D = u32, B = VecBuckets<T>> {
+    heap: FixedHammingHeap<T, D, B>,
+    distances: usize,
+}
+
+impl<T> FixedHammingMaxHeap<T> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Automatically initializes self with `distances` distances.
+    pub fn new_distances(distances: usize) -> Self {
+        let mut s = Self::new();
+        s.set_distances(distances);
+        s
+    }
+}
+
+impl<T, D, B> FixedHammingMaxHeap<T, D, B>
+where
+    D: Distance,
+    B: Buckets<T>,
+{
+    /// This sets the capacity of the queue to `cap`. If the capacity is lowered, this removes the nearest elements
+    /// to keep `size == cap`.
+    ///
+    /// Panics if `cap` is `0` or if `set_distances` was never called.
+    pub fn set_capacity(&mut self, cap: usize) {
+        self.heap.set_capacity(cap);
+    }
+
+    /// This sets the capacity of the queue to `cap` like `set_capacity`.
+    ///
+    /// Returns an error and leaves the heap unchanged if `cap` is `0` or if `set_distances` was never called.
+    pub fn try_set_capacity(&mut self, cap: usize) -> Result<(), HammingHeapError> {
+        self.heap.try_set_capacity(cap)
+    }
+
+    /// This removes the nearest elements until it reaches `len`.
+    pub fn set_len(&mut self, len: usize) {
+        self.heap.set_len(len);
+    }
+
+    /// Gets the `len` or `size` of the heap.
+    pub fn len(&self) -> usize {
+        self.heap.len()
+    }
+
+    /// Checks if the heap is empty.
+    pub fn is_empty(&self) -> bool {
+        self.heap.is_empty()
+    }
+
+    /// Clear the queue while maintaining the allocated memory.
+    ///
+    /// Panics if `set_distances` was never called.
+    pub fn clear(&mut self) {
+        self.heap.clear();
+    }
+
+    /// Clear the queue while maintaining the allocated memory.
+    ///
+    /// Returns an error if `set_distances` was never called.
+    pub fn try_clear(&mut self) -> Result<(), HammingHeapError> {
+        self.heap.try_clear()
+    }
+
+    /// Set number of distances. Also clears the heap.
+    ///
+    /// Panics if the largest distance can't be represented by `D`.
+    pub fn set_distances(&mut self, distances: usize) {
+        if let Err(e) = self.try_set_distances(distances) {
+            panic!("{}", e);
+        }
+    }
+
+    /// Set number of distances. Also clears the heap.
+    ///
+    /// Returns an error and leaves the heap unchanged if the largest distance can't be represented by `D`.
+    pub fn try_set_distances(&mut self, distances: usize) -> Result<(), HammingHeapError> {
+        self.heap.try_set_distances(distances)?;
+        self.distances = distances;
+        Ok(())
+    }
+
+    /// Sets the policy that decides which of the elements tied at a distance is evicted first.
+    pub fn set_tie_break(&mut self, tie_break: TieBreak<T>) {
+        self.heap.set_tie_break(tie_break);
+    }
+
+    /// Gets the policy that decides which of the elements tied at a distance is evicted first.
+    pub fn tie_break(&self) -> TieBreak<T> {
+        self.heap.tie_break()
+    }
+
+    /// Add a feature to the search.
+    ///
+    /// Returns true if it was added.
+    ///
+    /// Panics if `distance` is outside of the configured distances or if the capacity was never set.
+    pub fn push(&mut self, distance: D, item: T) -> bool {
+        match self.try_push(distance, item) {
+            Ok(added) => added,
+            Err(e) => panic!("{}", e),
+        }
+    }
+
+    /// Add a feature to the search.
+    ///
+    /// Returns `Ok(true)` if it was added. Returns an error and drops the item if `distance` is outside of the
+    /// configured distances or if the capacity was never set.
+    pub fn try_push(&mut self, distance: D, item: T) -> Result<bool, HammingHeapError> {
+        distance::check_index(distance, self.distances)?;
+        self.heap
+            .try_push(distance::flip(distance, self.distances), item)
+    }
+
+    /// Add a feature to the search, handing back any item that doesn't stay in the heap.
+    ///
+    /// Panics if `distance` is outside of the configured distances or if the capacity was never set.
+    pub fn push_evict(&mut self, distance: D, item: T) -> PushResult<T, D> {
+        match self.try_push_evict(distance, item) {
+            Ok(result) => result,
+            Err(e) => panic!("{}", e),
+        }
+    }
+
+    /// Add a feature to the search, handing back any item that doesn't stay in the heap.
+    ///
+    /// Returns an error and drops the item if `distance` is outside of the configured distances or if the capacity
+    /// was never set.
+    pub fn try_push_evict(
+        &mut self,
+        distance: D,
+        item: T,
+    ) -> Result<PushResult<T, D>, HammingHeapError> {
+        distance::check_index(distance, self.distances)?;
+        let distances = self.distances;
+        Ok(
+            match self
+                .heap
+                .try_push_evict(distance::flip(distance, distances), item)?
+            {
+                PushResult::Evicted(evicted, item) => {
+                    PushResult::Evicted(distance::flip(evicted, distances), item)
+                }
+                PushResult::EvictedTies(evicted, items) => {
+                    PushResult::EvictedTies(distance::flip(evicted, distances), items)
+                }
+                result => result,
+            },
+        )
+    }
+
+    /// Checks if an item at `distance` would be kept by `push`.
+    ///
+    /// Returns false if `distance` is outside of the configured distances or if the capacity was never set.
+    pub fn would_accept(&self, distance: D) -> bool {
+        distance.to_index() < self.distances
+            && self
+                .heap
+                .would_accept(distance::flip(distance, self.distances))
+    }
+
+    /// Add a feature to the search, only calling `make_item` if the feature will be kept.
+    ///
+    /// Panics if `distance` is outside of the configured distances or if the capacity was never set.
+    pub fn push_with<F>(&mut self, distance: D, make_item: F) -> bool
+    where
+        F: FnOnce() -> T,
+    {
+        match self.try_push_with(distance, make_item) {
+            Ok(added) => added,
+            Err(e) => panic!("{}", e),
+        }
+    }
+
+    /// Add a feature to the search, only calling `make_item` if the feature will be kept.
+    ///
+    /// Returns `Ok(true)` if it was added. Returns an error without calling `make_item` if `distance` is outside of
+    /// the configured distances or if the capacity was never set.
+    pub fn try_push_with<F>(&mut self, distance: D, make_item: F) -> Result<bool, HammingHeapError>
+    where
+        F: FnOnce() -> T,
+    {
+        distance::check_index(distance, self.distances)?;
+        self.heap
+            .try_push_with(distance::flip(distance, self.distances), make_item)
+    }
+
+    /// Gets the smallest distance in the queue currently, below which items are rejected once the cap is reached.
+    ///
+    /// This is initialized to `0` (which is the nearest possible distance) until `cap` elements have been inserted.
+    pub fn worst(&self) -> D {
+        if self.distances == 0 {
+            return self.heap.worst();
+        }
+        distance::flip(self.heap.worst(), self.distances)
+    }
+
+    /// Returns true if the cap has been reached.
+    pub fn at_cap(&self) -> bool {
+        self.heap.at_cap()
+    }
+
+    /// Fill a slice with the farthest elements and return the part of the slice written.
+    pub fn fill_slice<'a>(&self, s: &'a mut [T]) -> &'a mut [T]
+    where
+        T: Clone,
+    {
+        self.heap.fill_slice(s)
+    }
+
+    /// Fill a slice with the farthest elements along with their distances and return the part of the slice written.
+    pub fn fill_pairs<'a>(&self, s: &'a mut [(D, T)]) -> &'a mut [(D, T)]
+    where
+        T: Clone,
+    {
+        let filled = self.heap.fill_pairs(s);
+        for (distance, _) in filled.iter_mut() {
+            *distance = distance::flip(*distance, self.distances);
+        }
+        filled
+    }
+
+    /// Consumes the heap and returns every element in farthest-to-nearest order.
+    pub fn into_sorted_vec(self) -> Vec<(D, T)> {
+        let distances = self.distances;
+        self.heap
+            .into_sorted_vec()
+            .into_iter()
+            .map(|(distance, item)| (distance::flip(distance, distances), item))
+            .collect()
+    }
+
+    /// Iterate over the entire queue in farthest-to-nearest order.
+    pub fn iter(
+        &self,
+    ) -> impl DoubleEndedIterator<Item = (D, &T)> + ExactSizeIterator + FusedIterator + '_ {
+        let distances = self.distances;
+        self.heap
+            .iter()
+            .map(move |(distance, item)| (distance::flip(distance, distances), item))
+    }
+
+    /// Iterate over the entire queue mutably in farthest-to-nearest order.
+    pub fn iter_mut(
+        &mut self,
+    ) -> impl DoubleEndedIterator<Item = (D, &mut T)> + ExactSizeIterator + FusedIterator + '_ {
+        let distances = self.distances;
+        self.heap
+            .iter_mut()
+            .map(move |(distance, item)| (distance::flip(distance, distances), item))
+    }
+}
+
+impl<T, D, B> Default for FixedHammingMaxHeap<T, D, B>
+where
+    D: Distance,
+    B: Buckets<T>,
+{
+    fn default() -> Self {
+        Self {
+            heap: FixedHammingHeap::default(),
+            distances: 0,
+        }
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn test_fixed_max_heap() {
+    let mut candidates: FixedHammingMaxHeap<u32> = FixedHammingMaxHeap::new_distances(11);
+    candidates.set_capacity(3);
+    assert_eq!(candidates.worst(), 0);
+    for (ix, &distance) in [7, 2, 5, 9, 2, 10].iter().enumerate() {
+        candidates.push(distance, ix as u32);
+    }
+    assert!(candidates.at_cap());
+    assert_eq!(candidates.worst(), 7);
+    assert!(!candidates.would_accept(7));
+    assert!(candidates.would_accept(8));
+    assert_eq!(candidates.push_evict(8, 6), PushResult::Evicted(7, 0));
+    assert_eq!(candidates.push_evict(1, 7), PushResult::Rejected(7));
+    let mut pairs = [(0, 0); 4];
+    assert_eq!(candidates.fill_pairs(&mut pairs), [(10, 5), (9, 3), (8, 6)]);
+    let mut items = [0; 2];
+    assert_eq!(candidates.fill_slice(&mut items), [5, 3]);
+    assert_eq!(
+        candidates.iter().rev().map(|(d, _)| d).collect::<Vec<_>>(),
+        [8, 9, 10]
+    );
+    assert_eq!(
+        candidates.try_push(11, 8),
+        Err(HammingHeapError::DistanceOutOfRange {
+            distance: 11,
+            distances: 11
+        })
+    );
+    assert_eq!(candidates.into_sorted_vec(), [(10, 5), (9, 3), (8, 6)]);
+
+    let mut candidates: FixedHammingMaxHeap<u32> = FixedHammingMaxHeap::new_distances(11);
+    assert_eq!(
+        candidates.try_push_with(3, || unreachable!()),
+        Err(HammingHeapError::ZeroCapacity)
+    );
+}
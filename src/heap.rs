@@ -1,3 +1,13 @@
+use crate::buckets::{Buckets, VecBuckets};
+use crate::descriptor::{self, BinaryDescriptor, FixedBinaryDescriptor};
+use crate::distance::{self, Distance};
+use crate::iter::{BucketsIter, Iter, IterMut};
+use crate::occupancy::Occupancy;
+use crate::tie_break::{End, TieBreak};
+use crate::HammingHeapError;
+use core::marker::PhantomData;
+use core::ops::RangeBounds;
+
 /// This is a special heap specifically for hamming space searches.
 ///
 /// This queue works by having n-bits + 1 vectors, one for each hamming distance. When we find that any item
@@ -6,27 +16,85 @@
 /// distance priorities.
 ///
 /// We maintain the lowest weight vector at any given time in the queue. When a vector runs out,
-/// we iterate until we find the next-best non-empty distance vector.
+/// we find the next-best non-empty distance vector using a bitset of occupied distances, so sparse
+/// queues over wide descriptors don't pay for walking every empty distance.
 ///
-/// To use this you will need to call `set_distances` before use. This should be passed the maximum number of
-/// distances. Please keep in mind that the maximum number of hamming distances between an `n` bit number
-/// is `n + 1`. An example would be:
+/// To use this you will need to set the number of distances before use. The easiest way is to construct it with
+/// `for_descriptor`, which derives the number of distances from the width of a [`FixedBinaryDescriptor`], and to
+/// push with `push_descriptor`, which computes the Hamming distance for you:
 ///
 /// ```
-/// assert_eq!((0u128 ^ !0).count_ones(), 128);
+/// use hamming_heap::HammingHeap;
+/// let mut candidates: HammingHeap<()> = HammingHeap::for_descriptor::<u128>();
+/// candidates.push_descriptor(&0u128, &!0u128, ());
+/// assert_eq!(candidates.pop(), Some((128, ())));
 /// ```
 ///
-/// So make sure you use `n + 1` as your `distances` or else you may encounter a runtime panic.
+/// When calling `set_distances` yourself, keep in mind that an `n` bit descriptor has `n + 1` distances, because
+/// both `0` and `n` are possible distances.
+///
+/// If the distances come from untrusted input, use the `try_` methods to get a [`HammingHeapError`] instead:
 ///
 /// ```
-/// use hamming_heap::HammingHeap;
+/// use hamming_heap::{HammingHeap, HammingHeapError};
 /// let mut candidates = HammingHeap::new_distances(129);
+/// assert_eq!(
+///     candidates.try_push(129, ()),
+///     Err(HammingHeapError::DistanceOutOfRange { distance: 129, distances: 129 }),
+/// );
+/// ```
+///
+/// If the maximum distance isn't known up front, the heap can instead grow its distances on demand up to a
+/// hard limit:
+///
+/// ```
+/// use hamming_heap::HammingHeap;
+/// let mut candidates = HammingHeap::new_growable(513);
 /// candidates.push((0u128 ^ !0u128).count_ones(), ());
+/// candidates.push([!0u128; 4].iter().map(|n| n.count_ones()).sum::<u32>(), ());
+/// assert!(candidates.try_push(513, ()).is_err());
+/// ```
+///
+/// Searches that find a better distance for an item that is already queued can leave the old entry in place
+/// and skip it later. Use `mark_stale` to count the old entry and `pop_valid` to skip and drop stale entries:
+///
+/// ```
+/// use hamming_heap::HammingHeap;
+/// let mut distance_to = [u32::MAX; 3];
+/// let mut candidates = HammingHeap::new_distances(129);
+/// for &(distance, node) in &[(9, 0), (7, 1), (3, 0), (5, 2)] {
+///     if distance < distance_to[node] {
+///         if distance_to[node] != u32::MAX {
+///             // The entry already queued for this node is now stale.
+///             candidates.mark_stale(distance_to[node]);
+///         }
+///         distance_to[node] = distance;
+///         candidates.push(distance, (distance, node));
+///     }
+/// }
+/// let current = |&(distance, node): &(u32, usize)| distance_to[node] == distance;
+/// let mut order = vec![];
+/// while let Some((_, (_, node))) = candidates.pop_valid(current) {
+///     order.push(node);
+/// }
+/// assert_eq!(order, [0, 2, 1]);
+/// assert_eq!(candidates.tombstones(), 0);
 /// ```
+///
+/// Distances are `u32` by default, which is what `count_ones()` returns. Any [`Distance`] can be used instead.
+///
+/// The items are stored in [`VecBuckets`] by default. See [`Buckets`] for the other storage backends.
 #[derive(Clone, Debug)]
-pub struct HammingHeap<T> {
-    distances: Vec<Vec<T>>,
-    best: u32,
+pub struct HammingHeap<T, D = u32, B = VecBuckets<T>> {
+    distances: B,
+    occupied: Occupancy,
+    /// The number of entries at each distance that were marked stale but not yet dropped.
+    tombstones: Vec<usize>,
+    best: usize,
+    len: usize,
+    growth_limit: usize,
+    tie_break: TieBreak<T>,
+    _marker: PhantomData<(T, D)>,
 }
 
 impl<T> HammingHeap<T> {
@@ -41,12 +109,50 @@ impl<T> HammingHeap<T> {
         s
     }
 
+    /// Automatically initializes self with every distance possible between two `X` descriptors.
+    ///
+    /// Panics if the largest distance can't be represented by `D`.
+    pub fn for_descriptor<X>() -> Self
+    where
+        X: FixedBinaryDescriptor,
+    {
+        Self::new_distances(X::DISTANCES)
+    }
+
+    /// Automatically initializes self to grow its distances on demand up to `max_distances` distances.
+    pub fn new_growable(max_distances: usize) -> Self {
+        let mut s = Self::new();
+        s.set_growth_limit(max_distances);
+        s
+    }
+}
+
+impl<T, D, B> HammingHeap<T, D, B>
+where
+    D: Distance,
+    B: Buckets<T>,
+{
     /// This allows the queue to be cleared so that we don't need to reallocate memory.
     pub fn clear(&mut self) {
-        for v in self.distances[self.best as usize..].iter_mut() {
-            v.clear();
+        for distance in self.occupied.iter() {
+            self.distances.clear(distance);
+        }
+        self.occupied.clear();
+        for tombstones in &mut self.tombstones {
+            *tombstones = 0;
         }
         self.best = 0;
+        self.len = 0;
+    }
+
+    /// Gets the number of entries in the heap, including stale ones.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Checks if the heap is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
     }
 
     /// Set number of distances. Also clears the heap.
@@ -55,64 +161,648 @@ impl<T> HammingHeap<T> {
     ///
     /// If you have a 128-bit number, keep in mind that it has `129` distances because
     /// `128` is one of the possible distances.
+    ///
+    /// Panics if the largest distance can't be represented by `D`.
     pub fn set_distances(&mut self, distances: usize) {
-        self.distances.clear();
-        self.distances.resize_with(distances, || vec![]);
+        if let Err(e) = self.try_set_distances(distances) {
+            panic!("{}", e);
+        }
+    }
+
+    /// Set number of distances. Also clears the heap.
+    ///
+    /// Returns an error and leaves the heap unchanged if the largest distance can't be represented by `D`.
+    pub fn try_set_distances(&mut self, distances: usize) -> Result<(), HammingHeapError> {
+        distance::check_distances::<D>(distances)?;
+        self.distances.reset(distances);
+        self.occupied.reset(distances);
+        self.tombstones.clear();
+        self.tombstones.resize(distances, 0);
         self.best = 0;
+        self.len = 0;
+        Ok(())
+    }
+
+    /// Set every distance possible between two `X` descriptors. Also clears the heap.
+    ///
+    /// This is `for_descriptor` for heaps that were constructed another way, such as with a `D` other than `u32`.
+    ///
+    /// ```
+    /// use hamming_heap::HammingHeap;
+    /// let mut candidates: HammingHeap<(), u8> = HammingHeap::default();
+    /// candidates.set_descriptor::<u128>();
+    /// candidates.push_descriptor(&0u128, &!0u128, ());
+    /// assert!(candidates.try_set_descriptor::<[u8; 32]>().is_err());
+    /// ```
+    ///
+    /// Panics if the largest distance can't be represented by `D`.
+    pub fn set_descriptor<X>(&mut self)
+    where
+        X: FixedBinaryDescriptor,
+    {
+        self.set_distances(X::DISTANCES);
+    }
+
+    /// Set every distance possible between two `X` descriptors. Also clears the heap.
+    ///
+    /// Returns an error and leaves the heap unchanged if the largest distance can't be represented by `D`.
+    pub fn try_set_descriptor<X>(&mut self) -> Result<(), HammingHeapError>
+    where
+        X: FixedBinaryDescriptor,
+    {
+        self.try_set_distances(X::DISTANCES)
+    }
+
+    /// Allows pushing distances beyond the configured distances, growing the heap as needed
+    /// until it has `max_distances` distances. Pushing a distance that doesn't fit is still an error.
+    ///
+    /// Growing keeps the items already in the heap. A limit of `0`, the default, disables growth.
+    ///
+    /// Panics if the largest distance within the limit can't be represented by `D`.
+    pub fn set_growth_limit(&mut self, max_distances: usize) {
+        if let Err(e) = distance::check_distances::<D>(max_distances) {
+            panic!("{}", e);
+        }
+        self.growth_limit = max_distances;
+    }
+
+    /// Gets the maximum number of distances the heap will grow to on demand.
+    pub fn growth_limit(&self) -> usize {
+        self.growth_limit
+    }
+
+    /// This removes the nearest candidate from the queue.
+    ///
+    /// Returns an error if `set_distances` was never called and the heap can't grow.
+    #[inline]
+    pub fn try_pop(&mut self) -> Result<Option<(D, T)>, HammingHeapError> {
+        if self.distances.num_buckets() == 0 && self.growth_limit == 0 {
+            Err(HammingHeapError::DistancesNotSet)
+        } else {
+            Ok(self.pop())
+        }
     }
 
     /// This removes the nearest candidate from the queue.
+    ///
+    /// Candidates tied at the nearest distance are removed in the order of the [`TieBreak`] policy.
+    #[inline]
+    pub fn pop(&mut self) -> Option<(D, T)> {
+        let best = self.occupied.first_from(self.best)?;
+        self.best = best;
+        let node = if let TieBreak::Lifo = self.tie_break {
+            self.distances.pop(best)?
+        } else {
+            self.tie_break.take(&mut self.distances, best, End::Best)?
+        };
+        if self.distances.bucket(best).is_empty() {
+            self.occupied.remove(best);
+            self.tombstones[best] = 0;
+        }
+        self.len -= 1;
+        Some((distance::from_index(best), node))
+    }
+
+    /// Sets the policy that decides which of the candidates tied at a distance is popped first.
+    ///
+    /// The policy can be changed at any time. Switching to an `Order` policy sorts the candidates at every distance.
+    pub fn set_tie_break(&mut self, tie_break: TieBreak<T>) {
+        tie_break.sort(&mut self.distances);
+        self.tie_break = tie_break;
+    }
+
+    /// Gets the policy that decides which of the candidates tied at a distance is popped first.
+    pub fn tie_break(&self) -> TieBreak<T> {
+        self.tie_break
+    }
+
+    /// Removes the candidate at `index` in the occupied bucket `distance`.
+    #[inline]
+    fn remove(&mut self, distance: usize, index: usize) -> T {
+        let node = self.distances.remove(distance, index);
+        if self.distances.bucket(distance).is_empty() {
+            self.occupied.remove(distance);
+            self.tombstones[distance] = 0;
+        }
+        self.len -= 1;
+        node
+    }
+
+    /// This removes the nearest candidate from the queue if its distance is no worse than `max_distance`.
+    ///
+    /// Distances beyond `max_distance` are never looked at, so this stays cheap when the heap
+    /// holds many far away candidates.
     #[inline]
-    pub fn pop(&mut self) -> Option<(u32, T)> {
+    pub fn pop_within(&mut self, max_distance: D) -> Option<(D, T)> {
+        let best = self.occupied.first_in(self.best, max_distance.to_index())?;
+        self.best = best;
+        self.pop()
+    }
+
+    /// Removes every candidate at the best distance.
+    ///
+    /// Returns the best distance and an iterator that removes the candidates at that distance in the same order
+    /// as `pop`. Any candidates the iterator doesn't yield are dropped along with it.
+    ///
+    /// ```
+    /// use hamming_heap::HammingHeap;
+    /// let mut candidates = HammingHeap::new_distances(129);
+    /// candidates.push(4, 'a');
+    /// candidates.push(2, 'b');
+    /// candidates.push(2, 'c');
+    /// let (distance, items) = candidates.pop_bucket().unwrap();
+    /// assert_eq!(distance, 2);
+    /// assert_eq!(items.collect::<Vec<_>>(), ['c', 'b']);
+    /// assert_eq!(candidates.pop(), Some((4, 'a')));
+    /// ```
+    pub fn pop_bucket(&mut self) -> Option<(D, PopBucket<'_, T, D, B>)> {
+        let best = self.occupied.first_from(self.best)?;
+        self.best = best;
+        Some((
+            distance::from_index(best),
+            PopBucket {
+                heap: self,
+                distance: best,
+            },
+        ))
+    }
+
+    /// Moves up to `n` of the nearest candidates to the end of `out` in best-to-worse order.
+    ///
+    /// Returns the number of candidates moved, which is only less than `n` if the heap ran out.
+    pub fn pop_n(&mut self, n: usize, out: &mut Vec<(D, T)>) -> usize {
+        let mut moved = 0;
+        while moved < n {
+            let best = match self.occupied.first_from(self.best) {
+                Some(best) => best,
+                None => break,
+            };
+            self.best = best;
+            let distance = distance::from_index(best);
+            let take = core::cmp::min(n - moved, self.distances.bucket(best).len());
+            out.reserve(take);
+            for _ in 0..take {
+                let item = self
+                    .tie_break
+                    .take(&mut self.distances, best, End::Best)
+                    .expect("bucket had fewer items than its length");
+                out.push((distance, item));
+            }
+            if self.distances.bucket(best).is_empty() {
+                self.occupied.remove(best);
+                self.tombstones[best] = 0;
+            }
+            self.len -= take;
+            moved += take;
+        }
+        moved
+    }
+
+    /// This removes the nearest candidate for which `valid` returns true from the queue.
+    ///
+    /// Entries that are skipped are dropped. If more than half of the entries at a distance are marked stale when it
+    /// is reached, the whole distance is compacted at once.
+    pub fn pop_valid<F>(&mut self, valid: F) -> Option<(D, T)>
+    where
+        F: FnMut(&T) -> bool,
+    {
+        let (best, index) = self.skip_stale(valid)?;
+        Some((distance::from_index(best), self.remove(best, index)))
+    }
+
+    /// Returns the best distance that has a candidate for which `valid` returns true.
+    ///
+    /// Stale entries in front of that candidate are dropped, just like `pop_valid` would.
+    pub fn best_valid<F>(&mut self, valid: F) -> Option<D>
+    where
+        F: FnMut(&T) -> bool,
+    {
+        self.skip_stale(valid)
+            .map(|(best, _)| distance::from_index(best))
+    }
+
+    /// Iterate over the candidates with a distance in `range` in best-to-worse order.
+    pub fn iter_range<R>(&self, range: R) -> Iter<'_, T, D, B>
+    where
+        R: RangeBounds<D>,
+    {
+        let range = distance::index_range(&range, self.distances.num_buckets());
+        Iter::counted(&self.distances, &self.occupied, range)
+    }
+
+    /// Iterate over the candidates for which `valid` returns true in best-to-worse order.
+    pub fn iter_valid<'a, F>(&'a self, mut valid: F) -> impl Iterator<Item = (D, &'a T)> + 'a
+    where
+        F: FnMut(&T) -> bool + 'a,
+    {
+        self.iter().filter(move |&(_, item)| valid(item))
+    }
+
+    /// Records that one entry at `distance` no longer needs to be popped.
+    ///
+    /// This doesn't remove anything by itself. The count is used to compact distances that are mostly stale
+    /// once `pop_valid`, `best_valid`, or `compact` reaches them. Distances that hold nothing are ignored.
+    pub fn mark_stale(&mut self, distance: D) {
+        let distance = distance.to_index();
+        if distance < self.distances.num_buckets() && !self.distances.bucket(distance).is_empty() {
+            self.tombstones[distance] += 1;
+        }
+    }
+
+    /// Gets the number of entries marked stale that haven't been dropped yet.
+    pub fn tombstones(&self) -> usize {
+        self.tombstones.iter().sum()
+    }
+
+    /// Drops every entry for which `valid` returns false and resets the stale counts.
+    pub fn compact<F>(&mut self, mut valid: F)
+    where
+        F: FnMut(&T) -> bool,
+    {
+        let mut next = self.occupied.first_from(0);
+        while let Some(distance) = next {
+            self.compact_distance(distance, &mut valid);
+            next = self.occupied.first_from(distance + 1);
+        }
+    }
+
+    /// Drops stale entries from the best distances until the next entry to pop is valid, and returns the distance
+    /// and index of that entry.
+    fn skip_stale<F>(&mut self, mut valid: F) -> Option<(usize, usize)>
+    where
+        F: FnMut(&T) -> bool,
+    {
         loop {
-            if let Some(node) = self.distances[self.best as usize].pop() {
-                return Some((self.best, node));
-            } else if self.best == self.distances.len() as u32 - 1 {
-                return None;
-            } else {
-                self.best += 1;
+            let best = self.occupied.first_from(self.best)?;
+            self.best = best;
+            if self.tombstones[best] * 2 > self.distances.bucket(best).len() {
+                self.compact_distance(best, &mut valid);
+                continue;
+            }
+            let items = self.distances.bucket(best);
+            let index = self.tie_break.select(items, End::Best)?;
+            if valid(&items[index]) {
+                return Some((best, index));
             }
+            self.remove(best, index);
+            self.tombstones[best] = self.tombstones[best].saturating_sub(1);
+        }
+    }
+
+    /// Drops every entry at `distance` for which `valid` returns false.
+    fn compact_distance<F>(&mut self, distance: usize, valid: F)
+    where
+        F: FnMut(&T) -> bool,
+    {
+        let before = self.distances.bucket(distance).len();
+        self.distances.retain(distance, valid);
+        let after = self.distances.bucket(distance).len();
+        if after == 0 {
+            self.occupied.remove(distance);
+        }
+        self.tombstones[distance] = 0;
+        self.len -= before - after;
+    }
+
+    /// Inserts a node.
+    ///
+    /// Panics if `distance` is outside of the configured distances.
+    #[inline]
+    pub fn push(&mut self, distance: D, node: T) {
+        let distance = distance.to_index();
+        if distance >= self.distances.num_buckets() {
+            self.grow_for_push(distance);
         }
+        self.push_index(distance, node);
     }
 
     /// Inserts a node.
+    ///
+    /// Returns an error and drops the node if `distance` is outside of the configured distances
+    /// and beyond the growth limit.
     #[inline]
-    pub fn push(&mut self, distance: u32, node: T) {
+    pub fn try_push(&mut self, distance: D, node: T) -> Result<(), HammingHeapError> {
+        let distances = self.distances.num_buckets();
+        let distance = distance::check_index(distance, distances.max(self.growth_limit))?;
+        if distance >= distances {
+            self.grow(distance + 1);
+        }
+        self.push_index(distance, node);
+        Ok(())
+    }
+
+    /// Inserts a node at the bucket index `distance`, which must already be within the configured distances.
+    #[inline]
+    fn push_index(&mut self, distance: usize, node: T) {
         if distance < self.best {
             self.best = distance;
         }
-        self.distances[distance as usize].push(node);
+        if let TieBreak::Lifo = self.tie_break {
+            self.distances.push(distance, node);
+        } else {
+            self.tie_break.insert(&mut self.distances, distance, node);
+        }
+        self.occupied.insert(distance);
+        self.len += 1;
+    }
+
+    /// Inserts a node at the Hamming distance between `query` and `feature`.
+    ///
+    /// Panics if the distance is outside of the configured distances.
+    #[inline]
+    pub fn push_descriptor<X>(&mut self, query: &X, feature: &X, node: T)
+    where
+        X: BinaryDescriptor + ?Sized,
+    {
+        if let Err(e) = self.try_push_descriptor(query, feature, node) {
+            panic!("{}", e);
+        }
+    }
+
+    /// Inserts a node at the Hamming distance between `query` and `feature`.
+    ///
+    /// Returns an error and drops the node if the distance is outside of the configured distances.
+    #[inline]
+    pub fn try_push_descriptor<X>(
+        &mut self,
+        query: &X,
+        feature: &X,
+        node: T,
+    ) -> Result<(), HammingHeapError>
+    where
+        X: BinaryDescriptor + ?Sized,
+    {
+        let distances = self.distances.num_buckets().max(self.growth_limit);
+        let distance = descriptor::distance_between(query, feature, distances)?;
+        self.try_push(distance, node)
+    }
+
+    /// Grows to fit the bucket index `distance` for `push`, panicking if it is beyond the growth limit.
+    #[cold]
+    #[inline(never)]
+    fn grow_for_push(&mut self, distance: usize) {
+        if distance >= self.growth_limit {
+            panic!(
+                "{}",
+                distance::index_error(
+                    distance,
+                    self.distances.num_buckets().max(self.growth_limit)
+                )
+            );
+        }
+        self.grow(distance + 1);
+    }
+
+    /// Adds distances until there are `distances` distances without removing anything.
+    #[cold]
+    fn grow(&mut self, distances: usize) {
+        self.distances.grow(distances);
+        self.occupied.grow(distances);
+        self.tombstones.resize(distances, 0);
     }
 
     /// Returns the best distance if not empty.
-    pub fn best(&self) -> Option<u32> {
-        self.distances[self.best as usize..]
-            .iter()
-            .position(|v| !v.is_empty())
-            .map(|n| n as u32 + self.best)
+    pub fn best(&self) -> Option<D> {
+        self.occupied
+            .first_from(self.best)
+            .map(distance::from_index)
     }
 
     /// Iterate over the entire queue in best-to-worse order.
-    pub fn iter(&self) -> impl Iterator<Item = (u32, &T)> {
-        self.distances[self.best as usize..]
-            .iter()
-            .enumerate()
-            .flat_map(|(distance, v)| v.iter().map(move |item| (distance as u32, item)))
+    pub fn iter(&self) -> Iter<'_, T, D, B> {
+        let range = self.best..self.distances.num_buckets();
+        Iter::new(&self.distances, &self.occupied, range, self.len)
     }
+
     /// Iterate over the entire queue in best-to-worse order.
-    pub fn iter_mut(&mut self) -> impl Iterator<Item = (u32, &mut T)> {
-        self.distances[self.best as usize..]
-            .iter_mut()
-            .enumerate()
-            .flat_map(|(distance, v)| v.iter_mut().map(move |item| (distance as u32, item)))
+    pub fn iter_mut(&mut self) -> IterMut<'_, T, D, B> {
+        let range = self.best..self.distances.num_buckets();
+        IterMut::new(&mut self.distances, range, self.len)
+    }
+
+    /// Iterate over the distances that have candidates in best-to-worse order, along with their candidates.
+    pub fn buckets(&self) -> BucketsIter<'_, T, D, B> {
+        let range = self.best..self.distances.num_buckets();
+        BucketsIter::new(&self.distances, &self.occupied, range)
+    }
+
+    /// Gets the candidates at `distance`.
+    ///
+    /// The slice is empty if `distance` is outside of the configured distances.
+    pub fn bucket(&self, distance: D) -> &[T] {
+        let distance = distance.to_index();
+        if distance < self.distances.num_buckets() {
+            self.distances.bucket(distance)
+        } else {
+            &[]
+        }
+    }
+
+    /// Gets the candidates at `distance` mutably.
+    ///
+    /// The slice is empty if `distance` is outside of the configured distances.
+    pub fn bucket_mut(&mut self, distance: D) -> &mut [T] {
+        let distance = distance.to_index();
+        if distance < self.distances.num_buckets() {
+            self.distances.bucket_mut(distance)
+        } else {
+            &mut []
+        }
+    }
+}
+
+/// Removes the candidates at one distance of a [`HammingHeap`].
+///
+/// This is created by [`HammingHeap::pop_bucket`].
+pub struct PopBucket<'a, T, D, B>
+where
+    D: Distance,
+    B: Buckets<T>,
+{
+    heap: &'a mut HammingHeap<T, D, B>,
+    distance: usize,
+}
+
+impl<T, D, B> Iterator for PopBucket<'_, T, D, B>
+where
+    D: Distance,
+    B: Buckets<T>,
+{
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        let heap = &mut *self.heap;
+        let item = heap
+            .tie_break
+            .take(&mut heap.distances, self.distance, End::Best)?;
+        heap.len -= 1;
+        if heap.distances.bucket(self.distance).is_empty() {
+            heap.occupied.remove(self.distance);
+            heap.tombstones[self.distance] = 0;
+        }
+        Some(item)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = self.heap.distances.bucket(self.distance).len();
+        (len, Some(len))
     }
 }
 
-impl<T> Default for HammingHeap<T> {
+impl<T, D, B> ExactSizeIterator for PopBucket<'_, T, D, B>
+where
+    D: Distance,
+    B: Buckets<T>,
+{
+}
+
+impl<T, D, B> Drop for PopBucket<'_, T, D, B>
+where
+    D: Distance,
+    B: Buckets<T>,
+{
+    fn drop(&mut self) {
+        let heap = &mut *self.heap;
+        heap.len -= heap.distances.bucket(self.distance).len();
+        heap.distances.clear(self.distance);
+        heap.occupied.remove(self.distance);
+        heap.tombstones[self.distance] = 0;
+    }
+}
+
+impl<T, D, B> Default for HammingHeap<T, D, B>
+where
+    D: Distance,
+    B: Buckets<T>,
+{
     fn default() -> Self {
         Self {
-            distances: vec![],
+            distances: B::default(),
+            occupied: Occupancy::default(),
+            tombstones: vec![],
             best: 0,
+            len: 0,
+            growth_limit: 0,
+            tie_break: TieBreak::default(),
+            _marker: PhantomData,
         }
     }
 }
+
+#[cfg(test)]
+#[test]
+fn test_growth() {
+    use crate::FlatBuckets;
+    let mut candidates: HammingHeap<u32, u32, FlatBuckets<u32>> = HammingHeap::default();
+    candidates.set_growth_limit(300);
+    candidates.push(40, 0);
+    candidates.push(7, 1);
+    assert_eq!(candidates.pop(), Some((7, 1)));
+    candidates.push(299, 2);
+    candidates.push(41, 3);
+    assert!(candidates.try_push(300, 4).is_err());
+    assert_eq!(candidates.best(), Some(40));
+    assert_eq!(candidates.pop(), Some((40, 0)));
+    assert_eq!(candidates.pop(), Some((41, 3)));
+    assert_eq!(candidates.pop(), Some((299, 2)));
+    assert_eq!(candidates.pop(), None);
+}
+
+#[cfg(test)]
+#[test]
+fn test_compaction() {
+    let mut candidates: HammingHeap<u32> = HammingHeap::new_distances(9);
+    for node in 0..10 {
+        candidates.push(4, node);
+    }
+    candidates.push(6, 10);
+    // Mark most of distance 4 stale so it gets compacted instead of popped one at a time.
+    for _ in 0..6 {
+        candidates.mark_stale(4);
+    }
+    let valid = |&node: &u32| node % 3 == 0;
+    assert_eq!(candidates.best_valid(valid), Some(4));
+    assert_eq!(candidates.len(), 5);
+    assert_eq!(candidates.tombstones(), 0);
+    assert_eq!(candidates.pop_valid(valid), Some((4, 9)));
+    assert_eq!(candidates.pop_valid(valid), Some((4, 6)));
+    assert_eq!(candidates.iter_valid(valid).count(), 2);
+    candidates.compact(valid);
+    assert_eq!(candidates.len(), 2);
+    assert_eq!(candidates.pop(), Some((4, 3)));
+    assert_eq!(candidates.pop(), Some((4, 0)));
+    assert_eq!(candidates.pop_valid(valid), None);
+    assert!(candidates.is_empty());
+}
+
+#[cfg(test)]
+#[test]
+fn test_stale_counts_reset() {
+    let mut candidates: HammingHeap<u32> = HammingHeap::new_distances(9);
+    // Nothing is at distance 2, so there is nothing to mark stale.
+    candidates.mark_stale(2);
+    assert_eq!(candidates.tombstones(), 0);
+    candidates.push(4, 0);
+    candidates.mark_stale(4);
+    assert_eq!(candidates.tombstones(), 1);
+    assert_eq!(candidates.pop(), Some((4, 0)));
+    assert_eq!(candidates.tombstones(), 0);
+    candidates.clear();
+    assert_eq!(candidates.tombstones(), 0);
+
+    candidates.push(4, 1);
+    candidates.push(5, 2);
+    candidates.mark_stale(4);
+    candidates.mark_stale(5);
+    let mut out = vec![];
+    candidates.pop_n(1, &mut out);
+    assert_eq!(candidates.tombstones(), 1);
+    candidates.clear();
+    assert_eq!(candidates.tombstones(), 0);
+}
+
+#[cfg(test)]
+#[test]
+fn test_pop_n() {
+    let mut candidates: HammingHeap<u32> = HammingHeap::new_distances(9);
+    for &(distance, node) in &[(5, 0), (2, 1), (2, 2), (8, 3), (5, 4)] {
+        candidates.push(distance, node);
+    }
+    let mut out = vec![];
+    assert_eq!(candidates.pop_n(3, &mut out), 3);
+    assert_eq!(out, [(2, 2), (2, 1), (5, 4)]);
+    out.clear();
+    assert_eq!(candidates.pop_n(3, &mut out), 2);
+    assert_eq!(out, [(5, 0), (8, 3)]);
+    assert!(candidates.is_empty());
+
+    candidates.push(3, 5);
+    candidates.push(3, 6);
+    candidates.push(7, 7);
+    {
+        let (distance, mut items) = candidates.pop_bucket().unwrap();
+        assert_eq!(distance, 3);
+        assert_eq!(items.len(), 2);
+        assert_eq!(items.next(), Some(6));
+    }
+    assert_eq!(candidates.len(), 1);
+    assert_eq!(candidates.best(), Some(7));
+}
+
+#[cfg(test)]
+#[test]
+fn test_pop_within() {
+    let mut candidates: HammingHeap<u32> = HammingHeap::new_distances(257);
+    candidates.push(200, 0);
+    candidates.push(3, 1);
+    candidates.push(70, 2);
+    assert_eq!(
+        candidates.iter_range(3..200).collect::<Vec<_>>(),
+        [(3, &1), (70, &2)]
+    );
+    assert_eq!(candidates.pop_within(69), Some((3, 1)));
+    assert_eq!(candidates.pop_within(69), None);
+    assert_eq!(candidates.pop_within(70), Some((70, 2)));
+    assert_eq!(candidates.pop_within(199), None);
+    assert_eq!(candidates.len(), 1);
+}
@@ -0,0 +1,356 @@
+use crate::distance::{self, Distance};
+use crate::occupancy::Occupancy;
+use crate::HammingHeapError;
+use core::marker::PhantomData;
+
+/// Refers to an item in an [`IndexedHammingHeap`].
+///
+/// A handle stays valid until its item is popped or removed. After that, the heap treats it as stale, even if the
+/// slot it referred to is reused by a later push.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Handle {
+    index: usize,
+    generation: usize,
+}
+
+#[derive(Clone, Debug)]
+struct Entry<T> {
+    item: T,
+    /// The bucket this entry is in.
+    distance: usize,
+    /// The position of this entry within its bucket.
+    position: usize,
+}
+
+#[derive(Clone, Debug)]
+struct Slot<T> {
+    generation: usize,
+    entry: Option<Entry<T>>,
+}
+
+/// A [`HammingHeap`](crate::HammingHeap) that gives every pushed item a [`Handle`].
+///
+/// With a handle, an item can be moved to a better distance with `decrease_key` or taken out with `remove` in
+/// constant time. This makes the heap a bucket queue suitable for Dijkstra-style graph searches, where finding
+/// a shorter path to a node that is already queued would otherwise mean pushing a duplicate and skipping the stale
+/// entry when it is popped.
+///
+/// ```
+/// use hamming_heap::IndexedHammingHeap;
+/// let mut candidates = IndexedHammingHeap::new_distances(129);
+/// let a = candidates.push(40, 'a');
+/// candidates.push(20, 'b');
+/// assert!(candidates.decrease_key(a, 10));
+/// assert_eq!(candidates.pop(), Some((10, 'a')));
+/// assert_eq!(candidates.pop(), Some((20, 'b')));
+/// assert!(!candidates.contains(a));
+/// ```
+#[derive(Clone, Debug)]
+pub struct IndexedHammingHeap<T, D = u32> {
+    slots: Vec<Slot<T>>,
+    /// Indices of slots that don't hold an entry.
+    free: Vec<usize>,
+    /// The slot index of every entry at each distance.
+    distances: Vec<Vec<usize>>,
+    occupied: Occupancy,
+    best: usize,
+    len: usize,
+    _marker: PhantomData<D>,
+}
+
+impl<T> IndexedHammingHeap<T> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Automatically initializes self with `distances` distances.
+    pub fn new_distances(distances: usize) -> Self {
+        let mut s = Self::new();
+        s.set_distances(distances);
+        s
+    }
+}
+
+impl<T, D> IndexedHammingHeap<T, D>
+where
+    D: Distance,
+{
+    /// Set number of distances. Also clears the heap.
+    ///
+    /// Panics if the largest distance can't be represented by `D`.
+    pub fn set_distances(&mut self, distances: usize) {
+        if let Err(e) = self.try_set_distances(distances) {
+            panic!("{}", e);
+        }
+    }
+
+    /// Set number of distances. Also clears the heap.
+    ///
+    /// Returns an error and leaves the heap unchanged if the largest distance can't be represented by `D`.
+    pub fn try_set_distances(&mut self, distances: usize) -> Result<(), HammingHeapError> {
+        distance::check_distances::<D>(distances)?;
+        self.clear();
+        self.distances.clear();
+        self.distances.resize_with(distances, Vec::new);
+        self.occupied.reset(distances);
+        Ok(())
+    }
+
+    /// Removes every item while maintaining the allocated memory.
+    ///
+    /// All handles given out so far become stale.
+    pub fn clear(&mut self) {
+        for distance in self.occupied.iter() {
+            self.distances[distance].clear();
+        }
+        self.occupied.clear();
+        self.free.clear();
+        for (index, slot) in self.slots.iter_mut().enumerate() {
+            if slot.entry.take().is_some() {
+                slot.generation += 1;
+            }
+            self.free.push(index);
+        }
+        self.best = 0;
+        self.len = 0;
+    }
+
+    /// Gets the number of items in the heap.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Checks if the heap is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Inserts an item and returns its handle.
+    ///
+    /// Panics if `distance` is outside of the configured distances.
+    pub fn push(&mut self, distance: D, item: T) -> Handle {
+        match self.try_push(distance, item) {
+            Ok(handle) => handle,
+            Err(e) => panic!("{}", e),
+        }
+    }
+
+    /// Inserts an item and returns its handle.
+    ///
+    /// Returns an error and drops the item if `distance` is outside of the configured distances.
+    pub fn try_push(&mut self, distance: D, item: T) -> Result<Handle, HammingHeapError> {
+        let distance = distance::check_index(distance, self.distances.len())?;
+        let entry = Entry {
+            item,
+            distance,
+            position: self.distances[distance].len(),
+        };
+        let index = match self.free.pop() {
+            Some(index) => {
+                self.slots[index].entry = Some(entry);
+                index
+            }
+            None => {
+                self.slots.push(Slot {
+                    generation: 0,
+                    entry: Some(entry),
+                });
+                self.slots.len() - 1
+            }
+        };
+        self.insert(distance, index);
+        self.len += 1;
+        Ok(Handle {
+            index,
+            generation: self.slots[index].generation,
+        })
+    }
+
+    /// Removes the nearest item from the queue.
+    pub fn pop(&mut self) -> Option<(D, T)> {
+        let best = self.occupied.first_from(self.best)?;
+        self.best = best;
+        let index = *self.distances[best].last()?;
+        self.take(index)
+    }
+
+    /// Returns the best distance if not empty.
+    pub fn best(&self) -> Option<D> {
+        self.occupied
+            .first_from(self.best)
+            .map(distance::from_index)
+    }
+
+    /// Checks if `handle` still refers to an item in the heap.
+    pub fn contains(&self, handle: Handle) -> bool {
+        self.entry(handle).is_some()
+    }
+
+    /// Gets the distance and item that `handle` refers to.
+    pub fn get(&self, handle: Handle) -> Option<(D, &T)> {
+        self.entry(handle)
+            .map(|entry| (distance::from_index(entry.distance), &entry.item))
+    }
+
+    /// Gets the distance and item that `handle` refers to mutably.
+    pub fn get_mut(&mut self, handle: Handle) -> Option<(D, &mut T)> {
+        let slot = self.slots.get_mut(handle.index)?;
+        if slot.generation != handle.generation {
+            return None;
+        }
+        slot.entry
+            .as_mut()
+            .map(|entry| (distance::from_index(entry.distance), &mut entry.item))
+    }
+
+    /// Moves the item that `handle` refers to to `distance` if that is better than its current distance.
+    ///
+    /// Returns true if the item was moved. Returns false if the handle is stale or the item is already at
+    /// `distance` or better.
+    pub fn decrease_key(&mut self, handle: Handle, distance: D) -> bool {
+        let new_distance = distance.to_index();
+        let old_distance = match self.entry(handle) {
+            Some(entry) if new_distance < entry.distance => entry.distance,
+            _ => return false,
+        };
+        self.unlink(old_distance, handle.index);
+        self.slots[handle.index]
+            .entry
+            .as_mut()
+            .expect("handle was checked above")
+            .distance = new_distance;
+        self.insert(new_distance, handle.index);
+        true
+    }
+
+    /// Removes the item that `handle` refers to.
+    ///
+    /// Returns `None` if the handle is stale.
+    pub fn remove(&mut self, handle: Handle) -> Option<(D, T)> {
+        self.entry(handle)?;
+        self.take(handle.index)
+    }
+
+    /// Iterate over the entire queue in best-to-worse order.
+    pub fn iter(&self) -> impl Iterator<Item = (D, Handle, &T)> {
+        self.occupied.iter().flat_map(move |distance| {
+            self.distances[distance].iter().map(move |&index| {
+                let slot = &self.slots[index];
+                let entry = slot.entry.as_ref().expect("bucket refers to an empty slot");
+                let handle = Handle {
+                    index,
+                    generation: slot.generation,
+                };
+                (distance::from_index(distance), handle, &entry.item)
+            })
+        })
+    }
+
+    fn entry(&self, handle: Handle) -> Option<&Entry<T>> {
+        let slot = self.slots.get(handle.index)?;
+        if slot.generation == handle.generation {
+            slot.entry.as_ref()
+        } else {
+            None
+        }
+    }
+
+    /// Adds the slot `index` to the end of the bucket `distance`.
+    fn insert(&mut self, distance: usize, index: usize) {
+        let bucket = &mut self.distances[distance];
+        self.slots[index]
+            .entry
+            .as_mut()
+            .expect("inserted an empty slot")
+            .position = bucket.len();
+        bucket.push(index);
+        self.occupied.insert(distance);
+        if distance < self.best {
+            self.best = distance;
+        }
+    }
+
+    /// Takes the slot `index` out of the bucket `distance` by moving the last entry of the bucket into its place.
+    fn unlink(&mut self, distance: usize, index: usize) {
+        let position = self.slots[index]
+            .entry
+            .as_ref()
+            .expect("unlinked an empty slot")
+            .position;
+        let bucket = &mut self.distances[distance];
+        bucket.swap_remove(position);
+        if let Some(&moved) = bucket.get(position) {
+            self.slots[moved]
+                .entry
+                .as_mut()
+                .expect("bucket refers to an empty slot")
+                .position = position;
+        }
+        if bucket.is_empty() {
+            self.occupied.remove(distance);
+        }
+    }
+
+    /// Removes the entry in slot `index` from the heap and frees the slot.
+    fn take(&mut self, index: usize) -> Option<(D, T)> {
+        let distance = self.slots[index].entry.as_ref()?.distance;
+        self.unlink(distance, index);
+        let slot = &mut self.slots[index];
+        let entry = slot.entry.take()?;
+        slot.generation += 1;
+        self.free.push(index);
+        self.len -= 1;
+        Some((distance::from_index(distance), entry.item))
+    }
+}
+
+impl<T, D> Default for IndexedHammingHeap<T, D>
+where
+    D: Distance,
+{
+    fn default() -> Self {
+        Self {
+            slots: vec![],
+            free: vec![],
+            distances: vec![],
+            occupied: Occupancy::default(),
+            best: 0,
+            len: 0,
+            _marker: PhantomData,
+        }
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn test_indexed_heap() {
+    let mut candidates: IndexedHammingHeap<char> = IndexedHammingHeap::new_distances(11);
+    let a = candidates.push(5, 'a');
+    let b = candidates.push(5, 'b');
+    let c = candidates.push(5, 'c');
+    let d = candidates.push(9, 'd');
+    assert!(candidates.decrease_key(a, 2));
+    assert!(!candidates.decrease_key(a, 3));
+    assert!(candidates.decrease_key(d, 4));
+    assert_eq!(candidates.remove(b), Some((5, 'b')));
+    assert_eq!(candidates.remove(b), None);
+    assert_eq!(candidates.get(c), Some((5, &'c')));
+    assert_eq!(candidates.len(), 3);
+    // The slot of `b` is reused, but `b` must stay stale.
+    let e = candidates.push(1, 'e');
+    assert!(!candidates.contains(b));
+    assert_eq!(
+        candidates
+            .iter()
+            .map(|(distance, _, &item)| (distance, item))
+            .collect::<Vec<_>>(),
+        vec![(1, 'e'), (2, 'a'), (4, 'd'), (5, 'c')]
+    );
+    assert_eq!(candidates.pop(), Some((1, 'e')));
+    assert!(!candidates.contains(e));
+    assert_eq!(candidates.pop(), Some((2, 'a')));
+    assert_eq!(candidates.pop(), Some((4, 'd')));
+    assert_eq!(candidates.pop(), Some((5, 'c')));
+    assert_eq!(candidates.pop(), None);
+    assert!(candidates.is_empty());
+}
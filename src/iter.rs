@@ -0,0 +1,373 @@
+use crate::buckets::Buckets;
+use crate::distance::{self, Distance};
+use crate::occupancy::Occupancy;
+use core::iter::FusedIterator;
+use core::marker::PhantomData;
+use core::ops::Range;
+use core::slice;
+
+/// Iterator over the items of a heap in best-to-worse order, along with their distance.
+///
+/// Only the occupied distances are visited, in either direction.
+pub struct Iter<'a, T, D, B> {
+    buckets: BucketsIter<'a, T, D, B>,
+    front: Option<(D, slice::Iter<'a, T>)>,
+    back: Option<(D, slice::Iter<'a, T>)>,
+    remaining: usize,
+}
+
+impl<'a, T, D, B> Iter<'a, T, D, B>
+where
+    D: Distance,
+    B: Buckets<T>,
+{
+    /// Iterates over the distances in `range`, which must contain `remaining` items in total.
+    pub(crate) fn new(
+        buckets: &'a B,
+        occupied: &'a Occupancy,
+        range: Range<usize>,
+        remaining: usize,
+    ) -> Self {
+        Self {
+            buckets: BucketsIter::new(buckets, occupied, range),
+            front: None,
+            back: None,
+            remaining,
+        }
+    }
+
+    /// Iterates over the distances in `range`, counting the items in them first.
+    pub(crate) fn counted(buckets: &'a B, occupied: &'a Occupancy, range: Range<usize>) -> Self {
+        let remaining = BucketsIter::<T, D, B>::new(buckets, occupied, range.clone())
+            .map(|(_, items)| items.len())
+            .sum();
+        Self::new(buckets, occupied, range, remaining)
+    }
+}
+
+impl<'a, T, D, B> Iterator for Iter<'a, T, D, B>
+where
+    D: Distance,
+    B: Buckets<T>,
+{
+    type Item = (D, &'a T);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some((distance, items)) = &mut self.front {
+                if let Some(item) = items.next() {
+                    self.remaining -= 1;
+                    return Some((*distance, item));
+                }
+            }
+            match self.buckets.next() {
+                Some((distance, items)) => self.front = Some((distance, items.iter())),
+                None => {
+                    let (distance, items) = self.back.as_mut()?;
+                    let item = items.next()?;
+                    self.remaining -= 1;
+                    return Some((*distance, item));
+                }
+            }
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<T, D, B> DoubleEndedIterator for Iter<'_, T, D, B>
+where
+    D: Distance,
+    B: Buckets<T>,
+{
+    fn next_back(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some((distance, items)) = &mut self.back {
+                if let Some(item) = items.next_back() {
+                    self.remaining -= 1;
+                    return Some((*distance, item));
+                }
+            }
+            match self.buckets.next_back() {
+                Some((distance, items)) => self.back = Some((distance, items.iter())),
+                None => {
+                    let (distance, items) = self.front.as_mut()?;
+                    let item = items.next_back()?;
+                    self.remaining -= 1;
+                    return Some((*distance, item));
+                }
+            }
+        }
+    }
+}
+
+impl<T, D, B> ExactSizeIterator for Iter<'_, T, D, B>
+where
+    D: Distance,
+    B: Buckets<T>,
+{
+}
+
+impl<T, D, B> FusedIterator for Iter<'_, T, D, B>
+where
+    D: Distance,
+    B: Buckets<T>,
+{
+}
+
+/// Iterator over the items of a heap mutably in best-to-worse order, along with their distance.
+pub struct IterMut<'a, T, D, B>
+where
+    B: Buckets<T> + 'a,
+    T: 'a,
+{
+    buckets: B::BucketsMut<'a>,
+    /// The distances that neither end has started on yet.
+    pending: Range<usize>,
+    front: (usize, slice::IterMut<'a, T>),
+    back: (usize, slice::IterMut<'a, T>),
+    remaining: usize,
+    _marker: PhantomData<D>,
+}
+
+impl<'a, T, D, B> IterMut<'a, T, D, B>
+where
+    D: Distance,
+    B: Buckets<T>,
+{
+    /// Iterates over the distances in `range`, which must contain `remaining` items in total.
+    pub(crate) fn new(buckets: &'a mut B, range: Range<usize>, remaining: usize) -> Self {
+        let total = buckets.num_buckets();
+        let mut buckets = buckets.buckets_mut();
+        // Skip the buckets outside of the range on both ends.
+        if range.start > 0 {
+            buckets.nth(range.start - 1);
+        }
+        if range.end < total {
+            buckets.nth_back(total - range.end - 1);
+        }
+        Self {
+            buckets,
+            pending: range,
+            front: (0, [].iter_mut()),
+            back: (0, [].iter_mut()),
+            remaining,
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<'a, T, D, B> Iterator for IterMut<'a, T, D, B>
+where
+    D: Distance,
+    B: Buckets<T>,
+{
+    type Item = (D, &'a mut T);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(item) = self.front.1.next() {
+                self.remaining -= 1;
+                return Some((distance::from_index(self.front.0), item));
+            }
+            match self.buckets.next() {
+                Some(items) => {
+                    self.front = (self.pending.start, items.iter_mut());
+                    self.pending.start += 1;
+                }
+                None => {
+                    let item = self.back.1.next()?;
+                    self.remaining -= 1;
+                    return Some((distance::from_index(self.back.0), item));
+                }
+            }
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<T, D, B> DoubleEndedIterator for IterMut<'_, T, D, B>
+where
+    D: Distance,
+    B: Buckets<T>,
+{
+    fn next_back(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(item) = self.back.1.next_back() {
+                self.remaining -= 1;
+                return Some((distance::from_index(self.back.0), item));
+            }
+            match self.buckets.next_back() {
+                Some(items) => {
+                    self.pending.end -= 1;
+                    self.back = (self.pending.end, items.iter_mut());
+                }
+                None => {
+                    let item = self.front.1.next_back()?;
+                    self.remaining -= 1;
+                    return Some((distance::from_index(self.front.0), item));
+                }
+            }
+        }
+    }
+}
+
+impl<T, D, B> ExactSizeIterator for IterMut<'_, T, D, B>
+where
+    D: Distance,
+    B: Buckets<T>,
+{
+}
+
+impl<T, D, B> FusedIterator for IterMut<'_, T, D, B>
+where
+    D: Distance,
+    B: Buckets<T>,
+{
+}
+
+/// Iterator over the occupied distances of a heap in best-to-worse order, along with the items at each.
+pub struct BucketsIter<'a, T, D, B> {
+    buckets: &'a B,
+    occupied: &'a Occupancy,
+    /// The distances that haven't been yielded from either end yet.
+    pending: Range<usize>,
+    _marker: PhantomData<(&'a T, D)>,
+}
+
+impl<'a, T, D, B> BucketsIter<'a, T, D, B>
+where
+    D: Distance,
+    B: Buckets<T>,
+{
+    pub(crate) fn new(buckets: &'a B, occupied: &'a Occupancy, range: Range<usize>) -> Self {
+        Self {
+            buckets,
+            occupied,
+            pending: range,
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<'a, T, D, B> Iterator for BucketsIter<'a, T, D, B>
+where
+    D: Distance,
+    B: Buckets<T>,
+    T: 'a,
+{
+    type Item = (D, &'a [T]);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.pending.is_empty() {
+            return None;
+        }
+        match self
+            .occupied
+            .first_in(self.pending.start, self.pending.end - 1)
+        {
+            Some(next) => {
+                self.pending.start = next + 1;
+                Some((distance::from_index(next), self.buckets.bucket(next)))
+            }
+            None => {
+                self.pending.start = self.pending.end;
+                None
+            }
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, Some(self.pending.len()))
+    }
+}
+
+impl<'a, T, D, B> DoubleEndedIterator for BucketsIter<'a, T, D, B>
+where
+    D: Distance,
+    B: Buckets<T>,
+    T: 'a,
+{
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if self.pending.is_empty() {
+            return None;
+        }
+        match self
+            .occupied
+            .last_to(self.pending.end - 1)
+            .filter(|&prev| prev >= self.pending.start)
+        {
+            Some(prev) => {
+                self.pending.end = prev;
+                Some((distance::from_index(prev), self.buckets.bucket(prev)))
+            }
+            None => {
+                self.pending.end = self.pending.start;
+                None
+            }
+        }
+    }
+}
+
+impl<'a, T, D, B> FusedIterator for BucketsIter<'a, T, D, B>
+where
+    D: Distance,
+    B: Buckets<T>,
+    T: 'a,
+{
+}
+
+#[cfg(test)]
+#[test]
+fn test_iterators() {
+    use crate::{FixedHammingHeap, HammingHeap};
+
+    let mut candidates: HammingHeap<u32> = HammingHeap::new_distances(200);
+    for &(distance, node) in &[(150, 0), (3, 1), (70, 2), (3, 3), (199, 4)] {
+        candidates.push(distance, node);
+    }
+    assert_eq!(candidates.pop(), Some((3, 3)));
+    // The distances must be absolute even though the best distance is no longer `0`.
+    let forward: Vec<_> = candidates.iter().map(|(d, &n)| (d, n)).collect();
+    assert_eq!(forward, [(3, 1), (70, 2), (150, 0), (199, 4)]);
+    let mut backward: Vec<_> = candidates.iter().rev().map(|(d, &n)| (d, n)).collect();
+    backward.reverse();
+    assert_eq!(backward, forward);
+    let mut iter = candidates.iter();
+    assert_eq!(iter.len(), 4);
+    assert_eq!(iter.next(), Some((3, &1)));
+    assert_eq!(iter.next_back(), Some((199, &4)));
+    assert_eq!(iter.len(), 2);
+    assert_eq!(iter.next_back(), Some((150, &0)));
+    assert_eq!(iter.next(), Some((70, &2)));
+    assert_eq!(iter.next(), None);
+    assert_eq!(iter.next_back(), None);
+
+    for (distance, node) in candidates.iter_mut().rev() {
+        *node += distance;
+    }
+    assert_eq!(candidates.bucket(70), [72]);
+    assert_eq!(
+        candidates
+            .buckets()
+            .rev()
+            .map(|(d, _)| d)
+            .collect::<Vec<_>>(),
+        [199, 150, 70, 3]
+    );
+
+    let mut candidates: FixedHammingHeap<u32> = FixedHammingHeap::new_distances(11);
+    candidates.set_capacity(3);
+    for (ix, &distance) in [7, 2, 5, 9, 2].iter().enumerate() {
+        candidates.push(distance, ix as u32);
+    }
+    assert_eq!(candidates.iter().len(), 3);
+    assert_eq!(candidates.iter_mut().next_back(), Some((5, &mut 2)));
+    assert_eq!(candidates.bucket(2), [1, 4]);
+    assert_eq!(candidates.buckets().count(), 2);
+}
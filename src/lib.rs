@@ -1,5 +1,30 @@
+mod buckets;
+mod descriptor;
+mod distance;
+mod double_ended_heap;
+mod error;
 mod fixed_heap;
+mod fixed_max_heap;
 mod heap;
+mod indexed_heap;
+mod iter;
+pub mod linear;
+mod max_heap;
+mod occupancy;
+mod popcount;
+#[cfg(test)]
+mod testing;
+mod tie_break;
 
-pub use fixed_heap::FixedHammingHeap;
-pub use heap::HammingHeap;
+pub use buckets::{Buckets, FlatBuckets, FlatBucketsMut, VecBuckets};
+pub use descriptor::{BinaryDescriptor, BoundedDistance, FixedBinaryDescriptor};
+pub use distance::Distance;
+pub use double_ended_heap::DoubleEndedHammingHeap;
+pub use error::HammingHeapError;
+pub use fixed_heap::{DrainSorted, FixedHammingHeap, PushResult};
+pub use fixed_max_heap::FixedHammingMaxHeap;
+pub use heap::{HammingHeap, PopBucket};
+pub use indexed_heap::{Handle, IndexedHammingHeap};
+pub use iter::{BucketsIter, Iter, IterMut};
+pub use max_heap::HammingMaxHeap;
+pub use tie_break::TieBreak;
@@ -0,0 +1,123 @@
+//! Brute-force nearest-neighbor search that compares a query against every feature.
+
+use crate::{FixedBinaryDescriptor, FixedHammingHeap};
+use core::marker::PhantomData;
+
+/// A feature found by a search, identified by its index in the searched slice.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Neighbor {
+    /// The Hamming distance between the query and the feature.
+    pub distance: u32,
+    /// The index of the feature in the searched slice.
+    pub index: usize,
+}
+
+/// Finds the `k` features nearest to `query`.
+///
+/// The neighbors are sorted by distance and then by index, and the lowest indices are kept among features tied at
+/// the `k`-th best distance. Fewer than `k` neighbors are returned if there are fewer than `k` features.
+///
+/// ```
+/// use hamming_heap::linear;
+/// let features = [0b1111u8, 0b0001, 0b0111, 0b0000];
+/// let neighbors = linear::knn(&0b0011, &features, 2);
+/// let found: Vec<(usize, u32)> = neighbors.iter().map(|n| (n.index, n.distance)).collect();
+/// assert_eq!(found, vec![(1, 1), (2, 1)]);
+/// ```
+pub fn knn<X>(query: &X, features: &[X], k: usize) -> Vec<Neighbor>
+where
+    X: FixedBinaryDescriptor,
+{
+    LinearSearcher::new().knn(query, features, k)
+}
+
+/// Searches for nearest neighbors like `knn` while keeping its allocations between queries.
+///
+/// ```
+/// use hamming_heap::linear::LinearSearcher;
+/// let features = [[0u64, 1], [3, 3], [0, 0]];
+/// let mut searcher = LinearSearcher::new();
+/// let mut neighbors = Vec::new();
+/// for query in &features {
+///     searcher.knn_into(query, &features, 2, &mut neighbors);
+///     assert_eq!(neighbors[0].distance, 0);
+/// }
+/// ```
+#[derive(Clone, Debug)]
+pub struct LinearSearcher<X> {
+    candidates: FixedHammingHeap<usize>,
+    _marker: PhantomData<fn(&X)>,
+}
+
+impl<X> LinearSearcher<X>
+where
+    X: FixedBinaryDescriptor,
+{
+    pub fn new() -> Self {
+        Self {
+            candidates: FixedHammingHeap::for_descriptor::<X>(),
+            _marker: PhantomData,
+        }
+    }
+
+    /// Finds the `k` features nearest to `query` like `knn`.
+    pub fn knn(&mut self, query: &X, features: &[X], k: usize) -> Vec<Neighbor> {
+        let mut neighbors = Vec::new();
+        self.knn_into(query, features, k, &mut neighbors);
+        neighbors
+    }
+
+    /// Finds the `k` features nearest to `query` like `knn`, replacing the contents of `neighbors` with them.
+    pub fn knn_into(&mut self, query: &X, features: &[X], k: usize, neighbors: &mut Vec<Neighbor>) {
+        neighbors.clear();
+        if k == 0 {
+            return;
+        }
+        self.candidates.clear();
+        self.candidates.set_capacity(k);
+        for (index, feature) in features.iter().enumerate() {
+            self.candidates.push_descriptor(query, feature, index);
+        }
+        // Ties are popped last in first out, so the lowest indices survive and each bucket is in index order.
+        neighbors.extend(
+            self.candidates
+                .iter()
+                .map(|(distance, &index)| Neighbor { distance, index }),
+        );
+    }
+}
+
+impl<X> Default for LinearSearcher<X>
+where
+    X: FixedBinaryDescriptor,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn test_knn_against_sort() {
+    use crate::testing::XorShift;
+
+    let mut rng = XorShift::new(0x3c6e_f372_fe94_f82b);
+    // Few bits so that there are plenty of ties at the cutoff.
+    let features: Vec<u16> = (0..300).map(|_| rng.next_u64() as u16).collect();
+    let mut searcher = LinearSearcher::new();
+    for k in [0, 1, 7, 64, 300, 400].iter().copied() {
+        let query = rng.next_u64() as u16;
+        let mut expected: Vec<Neighbor> = features
+            .iter()
+            .enumerate()
+            .map(|(index, feature)| Neighbor {
+                distance: (query ^ feature).count_ones(),
+                index,
+            })
+            .collect();
+        expected.sort();
+        expected.truncate(k);
+        assert_eq!(searcher.knn(&query, &features, k), expected);
+        assert_eq!(knn(&query, &features, k), expected);
+    }
+}
@@ -0,0 +1,242 @@
+use crate::buckets::{Buckets, VecBuckets};
+use crate::distance::{self, Distance};
+use crate::heap::HammingHeap;
+use crate::tie_break::TieBreak;
+use crate::HammingHeapError;
+use core::iter::FusedIterator;
+
+/// A [`HammingHeap`] that pops the largest key first.
+///
+/// This is for similarity-style keys where higher is better, like the popcount of an AND or the number of matched
+/// bits. It stores every key mirrored within the configured distances in a regular `HammingHeap`, so it shares the
+/// same bucket machinery and tracks the largest occupied key the same way `HammingHeap` tracks the smallest.
+///
+/// ```
+/// use hamming_heap::HammingMaxHeap;
+/// let mut candidates = HammingMaxHeap::new_distances(129);
+/// let query = 0b1011u128;
+/// for (node, &feature) in [0b0001u128, 0b1011, 0b1110].iter().enumerate() {
+///     candidates.push((query & feature).count_ones(), node);
+/// }
+/// assert_eq!(candidates.pop(), Some((3, 1)));
+/// assert_eq!(candidates.pop(), Some((2, 2)));
+/// assert_eq!(candidates.pop(), Some((1, 0)));
+/// ```
+///
+/// Since the mirroring depends on the number of distances, this heap can't grow its distances on demand.
+#[derive(Clone, Debug)]
+pub struct HammingMaxHeap<T, D = u32, B = VecBuckets<T>> {
+    heap: HammingHeap<T, D, B>,
+    distances: usize,
+}
+
+impl<T> HammingMaxHeap<T> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Automatically initializes self with `distances` distances.
+    pub fn new_distances(distances: usize) -> Self {
+        let mut s = Self::new();
+        s.set_distances(distances);
+        s
+    }
+}
+
+impl<T, D, B> HammingMaxHeap<T, D, B>
+where
+    D: Distance,
+    B: Buckets<T>,
+{
+    /// This allows the queue to be cleared so that we don't need to reallocate memory.
+    pub fn clear(&mut self) {
+        self.heap.clear();
+    }
+
+    /// Gets the number of items in the heap.
+    pub fn len(&self) -> usize {
+        self.heap.len()
+    }
+
+    /// Checks if the heap is empty.
+    pub fn is_empty(&self) -> bool {
+        self.heap.is_empty()
+    }
+
+    /// This sets the distances and clears the heap.
+    ///
+    /// Panics if the largest distance can't be represented by `D`.
+    pub fn set_distances(&mut self, distances: usize) {
+        if let Err(e) = self.try_set_distances(distances) {
+            panic!("{}", e);
+        }
+    }
+
+    /// This sets the distances and clears the heap.
+    ///
+    /// Returns an error and leaves the heap unchanged if the largest distance can't be represented by `D`.
+    pub fn try_set_distances(&mut self, distances: usize) -> Result<(), HammingHeapError> {
+        self.heap.try_set_distances(distances)?;
+        self.distances = distances;
+        Ok(())
+    }
+
+    /// Sets the policy that decides which of the candidates tied at a key is popped first.
+    pub fn set_tie_break(&mut self, tie_break: TieBreak<T>) {
+        self.heap.set_tie_break(tie_break);
+    }
+
+    /// Gets the policy that decides which of the candidates tied at a key is popped first.
+    pub fn tie_break(&self) -> TieBreak<T> {
+        self.heap.tie_break()
+    }
+
+    /// This removes the candidate with the largest key from the queue.
+    ///
+    /// Returns an error if `set_distances` was never called.
+    pub fn try_pop(&mut self) -> Result<Option<(D, T)>, HammingHeapError> {
+        if self.distances == 0 {
+            Err(HammingHeapError::DistancesNotSet)
+        } else {
+            Ok(self.pop())
+        }
+    }
+
+    /// This removes the candidate with the largest key from the queue.
+    #[inline]
+    pub fn pop(&mut self) -> Option<(D, T)> {
+        let distances = self.distances;
+        self.heap
+            .pop()
+            .map(|(distance, node)| (distance::flip(distance, distances), node))
+    }
+
+    /// This removes the candidate with the largest key from the queue if its key is no less than `min_distance`.
+    ///
+    /// Keys below `min_distance` are never looked at.
+    #[inline]
+    pub fn pop_within(&mut self, min_distance: D) -> Option<(D, T)> {
+        if min_distance.to_index() >= self.distances {
+            return None;
+        }
+        let distances = self.distances;
+        self.heap
+            .pop_within(distance::flip(min_distance, distances))
+            .map(|(distance, node)| (distance::flip(distance, distances), node))
+    }
+
+    /// Inserts a node.
+    ///
+    /// Panics if `distance` is outside of the configured distances.
+    #[inline]
+    pub fn push(&mut self, distance: D, node: T) {
+        if let Err(e) = self.try_push(distance, node) {
+            panic!("{}", e);
+        }
+    }
+
+    /// Inserts a node.
+    ///
+    /// Returns an error and drops the node if `distance` is outside of the configured distances.
+    #[inline]
+    pub fn try_push(&mut self, distance: D, node: T) -> Result<(), HammingHeapError> {
+        distance::check_index(distance, self.distances)?;
+        self.heap
+            .try_push(distance::flip(distance, self.distances), node)
+    }
+
+    /// Returns the largest key if not empty.
+    pub fn best(&self) -> Option<D> {
+        self.heap
+            .best()
+            .map(|distance| distance::flip(distance, self.distances))
+    }
+
+    /// Iterate over the entire queue in largest-to-smallest order.
+    pub fn iter(
+        &self,
+    ) -> impl DoubleEndedIterator<Item = (D, &T)> + ExactSizeIterator + FusedIterator + '_ {
+        let distances = self.distances;
+        self.heap
+            .iter()
+            .map(move |(distance, node)| (distance::flip(distance, distances), node))
+    }
+
+    /// Iterate over the entire queue mutably in largest-to-smallest order.
+    pub fn iter_mut(
+        &mut self,
+    ) -> impl DoubleEndedIterator<Item = (D, &mut T)> + ExactSizeIterator + FusedIterator + '_ {
+        let distances = self.distances;
+        self.heap
+            .iter_mut()
+            .map(move |(distance, node)| (distance::flip(distance, distances), node))
+    }
+
+    /// Gets the candidates at `distance`.
+    ///
+    /// Returns an empty slice if `distance` is outside of the configured distances.
+    pub fn bucket(&self, distance: D) -> &[T] {
+        if distance.to_index() < self.distances {
+            self.heap.bucket(distance::flip(distance, self.distances))
+        } else {
+            &[]
+        }
+    }
+
+    /// Gets the candidates at `distance` mutably.
+    ///
+    /// Returns an empty slice if `distance` is outside of the configured distances.
+    pub fn bucket_mut(&mut self, distance: D) -> &mut [T] {
+        if distance.to_index() < self.distances {
+            self.heap
+                .bucket_mut(distance::flip(distance, self.distances))
+        } else {
+            &mut []
+        }
+    }
+}
+
+impl<T, D, B> Default for HammingMaxHeap<T, D, B>
+where
+    D: Distance,
+    B: Buckets<T>,
+{
+    fn default() -> Self {
+        Self {
+            heap: HammingHeap::default(),
+            distances: 0,
+        }
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn test_max_heap() {
+    let mut candidates: HammingMaxHeap<char, u8> = HammingMaxHeap::default();
+    assert_eq!(candidates.try_pop(), Err(HammingHeapError::DistancesNotSet));
+    candidates.set_distances(9);
+    for &(distance, node) in &[(2, 'a'), (8, 'b'), (0, 'c'), (5, 'd'), (8, 'e')] {
+        candidates.push(distance, node);
+    }
+    assert_eq!(
+        candidates.try_push(9, 'f'),
+        Err(HammingHeapError::DistanceOutOfRange {
+            distance: 9,
+            distances: 9
+        })
+    );
+    assert_eq!(candidates.best(), Some(8));
+    assert_eq!(candidates.bucket(8), ['b', 'e']);
+    assert_eq!(
+        candidates.iter().rev().map(|(d, _)| d).collect::<Vec<_>>(),
+        [0, 2, 5, 8, 8]
+    );
+    assert_eq!(candidates.pop(), Some((8, 'e')));
+    assert_eq!(candidates.pop(), Some((8, 'b')));
+    assert_eq!(candidates.pop_within(3), Some((5, 'd')));
+    assert_eq!(candidates.pop_within(3), None);
+    assert_eq!(candidates.len(), 2);
+    assert_eq!(candidates.pop(), Some((2, 'a')));
+    assert_eq!(candidates.pop(), Some((0, 'c')));
+    assert_eq!(candidates.pop(), None);
+}
@@ -0,0 +1,129 @@
+/// A bitset that records which distance buckets are non-empty.
+///
+/// Finding the next non-empty bucket by walking the buckets themselves touches one `Vec` per distance.
+/// With `257` or `513` distances and only a handful of occupied buckets, that walk dominates `pop` and
+/// eviction. Instead, this lets the heaps jump straight to the next occupied bucket with a
+/// `trailing_zeros` or `leading_zeros` scan over 64 buckets at a time.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Occupancy {
+    words: Vec<u64>,
+}
+
+impl Occupancy {
+    /// Resizes the bitset to hold `bits` buckets and marks all of them empty.
+    pub(crate) fn reset(&mut self, bits: usize) {
+        self.words.clear();
+        self.words.resize(bits.div_ceil(64), 0);
+    }
+
+    /// Resizes the bitset to hold at least `bits` buckets without changing which are non-empty.
+    pub(crate) fn grow(&mut self, bits: usize) {
+        let words = bits.div_ceil(64);
+        if words > self.words.len() {
+            self.words.resize(words, 0);
+        }
+    }
+
+    /// Marks every bucket as empty without changing the size.
+    pub(crate) fn clear(&mut self) {
+        for word in &mut self.words {
+            *word = 0;
+        }
+    }
+
+    /// Marks bucket `ix` as non-empty.
+    #[inline]
+    pub(crate) fn insert(&mut self, ix: usize) {
+        self.words[ix / 64] |= 1 << (ix % 64);
+    }
+
+    /// Marks bucket `ix` as empty.
+    #[inline]
+    pub(crate) fn remove(&mut self, ix: usize) {
+        self.words[ix / 64] &= !(1 << (ix % 64));
+    }
+
+    /// Finds the lowest non-empty bucket that is `>= ix`.
+    #[inline]
+    pub(crate) fn first_from(&self, ix: usize) -> Option<usize> {
+        self.first_in(ix, usize::MAX)
+    }
+
+    /// Finds the lowest non-empty bucket in `lo..=hi` without looking at any words past `hi`.
+    #[inline]
+    pub(crate) fn first_in(&self, lo: usize, hi: usize) -> Option<usize> {
+        if lo > hi {
+            return None;
+        }
+        let mut word_ix = lo / 64;
+        let mut word = *self.words.get(word_ix)? & (!0 << (lo % 64));
+        loop {
+            if word != 0 {
+                let ix = word_ix * 64 + word.trailing_zeros() as usize;
+                return if ix <= hi { Some(ix) } else { None };
+            }
+            word_ix += 1;
+            if word_ix > hi / 64 {
+                return None;
+            }
+            word = *self.words.get(word_ix)?;
+        }
+    }
+
+    /// Finds the highest non-empty bucket that is `<= ix`.
+    #[inline]
+    pub(crate) fn last_to(&self, ix: usize) -> Option<usize> {
+        let mut word_ix = ix / 64;
+        let mut word = *self.words.get(word_ix)? & (!0 >> (63 - ix % 64));
+        loop {
+            if word != 0 {
+                return Some(word_ix * 64 + 63 - word.leading_zeros() as usize);
+            }
+            word_ix = word_ix.checked_sub(1)?;
+            word = self.words[word_ix];
+        }
+    }
+
+    /// Iterates over the non-empty buckets in ascending order.
+    pub(crate) fn iter(&self) -> impl Iterator<Item = usize> + '_ {
+        self.words.iter().enumerate().flat_map(|(word_ix, &word)| {
+            let mut word = word;
+            std::iter::from_fn(move || {
+                if word == 0 {
+                    None
+                } else {
+                    let bit = word.trailing_zeros() as usize;
+                    word &= word - 1;
+                    Some(word_ix * 64 + bit)
+                }
+            })
+        })
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn test_occupancy() {
+    let mut occupancy = Occupancy::default();
+    occupancy.reset(513);
+    assert_eq!(occupancy.first_from(0), None);
+    assert_eq!(occupancy.last_to(512), None);
+    for &ix in &[3, 63, 64, 200, 512] {
+        occupancy.insert(ix);
+    }
+    assert_eq!(occupancy.first_from(0), Some(3));
+    assert_eq!(occupancy.first_from(4), Some(63));
+    assert_eq!(occupancy.first_from(65), Some(200));
+    assert_eq!(occupancy.first_from(201), Some(512));
+    assert_eq!(occupancy.last_to(512), Some(512));
+    assert_eq!(occupancy.last_to(511), Some(200));
+    assert_eq!(occupancy.last_to(63), Some(63));
+    assert_eq!(occupancy.last_to(62), Some(3));
+    assert_eq!(occupancy.last_to(2), None);
+    assert_eq!(occupancy.first_in(4, 63), Some(63));
+    assert_eq!(occupancy.first_in(4, 62), None);
+    assert_eq!(occupancy.first_in(65, 199), None);
+    assert_eq!(occupancy.first_in(65, 511), Some(200));
+    occupancy.remove(63);
+    assert_eq!(occupancy.iter().collect::<Vec<_>>(), vec![3, 64, 200, 512]);
+}
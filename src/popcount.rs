@@ -0,0 +1,251 @@
+use crate::descriptor::BoundedDistance;
+#[cfg(target_arch = "x86_64")]
+use core::arch::x86_64::*;
+use core::convert::TryInto;
+
+/// Counts the differing bits of two byte strings of the same length with the fastest available kernel.
+///
+/// The widest kernel that the CPU supports is chosen at runtime, so the crate doesn't need to be compiled with
+/// `target-cpu=native` to benefit from it. The AVX-512 kernel is only considered with the `avx512` feature, which
+/// needs a newer compiler. Every kernel gives the same result as `scalar`.
+#[inline]
+pub(crate) fn hamming(a: &[u8], b: &[u8]) -> u32 {
+    debug_assert_eq!(a.len(), b.len());
+    #[cfg(target_arch = "x86_64")]
+    {
+        #[cfg(feature = "avx512")]
+        if a.len() >= 64
+            && is_x86_feature_detected!("avx512f")
+            && is_x86_feature_detected!("avx512vpopcntdq")
+        {
+            // SAFETY: The required CPU features were detected above.
+            return unsafe { avx512(a, b) };
+        }
+        if a.len() >= 32 && is_x86_feature_detected!("avx2") {
+            // SAFETY: The required CPU feature was detected above.
+            return unsafe { avx2(a, b) };
+        }
+    }
+    scalar(a, b)
+}
+
+/// Counts the differing bits of two word strings of the same length with the fastest available kernel.
+#[inline]
+pub(crate) fn hamming_words(a: &[u64], b: &[u64]) -> u32 {
+    hamming(as_bytes(a), as_bytes(b))
+}
+
+/// Views words as their bytes. The order of the bytes doesn't change how many bits are set.
+#[inline(always)]
+fn as_bytes(words: &[u64]) -> &[u8] {
+    // SAFETY: `u8` has no alignment requirement and every byte of a `u64` is initialized.
+    unsafe { core::slice::from_raw_parts(words.as_ptr().cast::<u8>(), words.len() * 8) }
+}
+
+/// Counts the differing bits of two byte strings of the same length a word at a time, stopping as soon as the
+/// count reaches `bound`.
+///
+/// A partial final word counts as a word. The word loop is compiled with `popcnt` when the CPU supports it.
+#[inline]
+pub(crate) fn hamming_bounded(a: &[u8], b: &[u8], bound: u32) -> BoundedDistance {
+    debug_assert_eq!(a.len(), b.len());
+    #[cfg(target_arch = "x86_64")]
+    {
+        if is_x86_feature_detected!("popcnt") {
+            // SAFETY: The required CPU feature was detected above.
+            return unsafe { popcnt_bounded(a, b, bound) };
+        }
+    }
+    scalar_bounded(a, b, bound)
+}
+
+/// Counts the differing bits of two word strings of the same length, stopping as soon as the count reaches
+/// `bound`.
+#[inline]
+pub(crate) fn hamming_words_bounded(a: &[u64], b: &[u64], bound: u32) -> BoundedDistance {
+    hamming_bounded(as_bytes(a), as_bytes(b), bound)
+}
+
+/// The portable kernel, which counts a word at a time.
+#[inline]
+pub(crate) fn scalar(a: &[u8], b: &[u8]) -> u32 {
+    let a_words = a.chunks_exact(8);
+    let b_words = b.chunks_exact(8);
+    let tail = a_words
+        .remainder()
+        .iter()
+        .zip(b_words.remainder())
+        .map(|(a, b)| (a ^ b).count_ones())
+        .sum::<u32>();
+    a_words
+        .zip(b_words)
+        .map(|(a, b)| {
+            let a = u64::from_ne_bytes(a.try_into().expect("chunk is 8 bytes"));
+            let b = u64::from_ne_bytes(b.try_into().expect("chunk is 8 bytes"));
+            (a ^ b).count_ones()
+        })
+        .sum::<u32>()
+        + tail
+}
+
+/// The portable bounded kernel, which counts a word at a time and checks the bound after each word.
+#[inline(always)]
+pub(crate) fn scalar_bounded(a: &[u8], b: &[u8], bound: u32) -> BoundedDistance {
+    let words = a.len().div_ceil(8);
+    let a_words = a.chunks_exact(8);
+    let b_words = b.chunks_exact(8);
+    let (a_tail, b_tail) = (a_words.remainder(), b_words.remainder());
+    let mut distance = 0;
+    for (word, (a, b)) in a_words.zip(b_words).enumerate() {
+        let a = u64::from_ne_bytes(a.try_into().expect("chunk is 8 bytes"));
+        let b = u64::from_ne_bytes(b.try_into().expect("chunk is 8 bytes"));
+        distance += (a ^ b).count_ones();
+        if distance >= bound {
+            return BoundedDistance::Abandoned {
+                skipped_words: words - word - 1,
+            };
+        }
+    }
+    distance += scalar(a_tail, b_tail);
+    if distance >= bound {
+        BoundedDistance::Abandoned { skipped_words: 0 }
+    } else {
+        BoundedDistance::Within(distance)
+    }
+}
+
+/// The portable bounded kernel compiled with the `popcnt` instruction.
+///
+/// # Safety
+///
+/// The CPU must support POPCNT.
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "popcnt")]
+pub(crate) unsafe fn popcnt_bounded(a: &[u8], b: &[u8], bound: u32) -> BoundedDistance {
+    scalar_bounded(a, b, bound)
+}
+
+/// Counts 32 bytes at a time by looking up the popcount of each nibble with `vpshufb`.
+///
+/// # Safety
+///
+/// The CPU must support AVX2.
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx2")]
+pub(crate) unsafe fn avx2(a: &[u8], b: &[u8]) -> u32 {
+    let lookup = _mm256_setr_epi8(
+        0, 1, 1, 2, 1, 2, 2, 3, 1, 2, 2, 3, 2, 3, 3, 4, 0, 1, 1, 2, 1, 2, 2, 3, 1, 2, 2, 3, 2, 3,
+        3, 4,
+    );
+    let low_mask = _mm256_set1_epi8(0x0f);
+    let mut total = _mm256_setzero_si256();
+    let chunks = a.len() / 32;
+    for chunk in 0..chunks {
+        let a = _mm256_loadu_si256(a.as_ptr().add(chunk * 32).cast());
+        let b = _mm256_loadu_si256(b.as_ptr().add(chunk * 32).cast());
+        let x = _mm256_xor_si256(a, b);
+        let low = _mm256_and_si256(x, low_mask);
+        let high = _mm256_and_si256(_mm256_srli_epi16(x, 4), low_mask);
+        let counts = _mm256_add_epi8(
+            _mm256_shuffle_epi8(lookup, low),
+            _mm256_shuffle_epi8(lookup, high),
+        );
+        // Each byte count is at most 8, so summing them into the four 64-bit lanes never overflows.
+        total = _mm256_add_epi64(total, _mm256_sad_epu8(counts, _mm256_setzero_si256()));
+    }
+    let mut lanes = [0u64; 4];
+    _mm256_storeu_si256(lanes.as_mut_ptr().cast(), total);
+    let tail = chunks * 32;
+    lanes.iter().sum::<u64>() as u32 + scalar(&a[tail..], &b[tail..])
+}
+
+/// Counts 64 bytes at a time with the AVX-512 `vpopcntq` instruction.
+///
+/// # Safety
+///
+/// The CPU must support AVX-512F and AVX-512 VPOPCNTDQ.
+#[cfg(all(target_arch = "x86_64", feature = "avx512"))]
+#[clippy::msrv = "1.89"]
+#[target_feature(enable = "avx512f,avx512vpopcntdq")]
+pub(crate) unsafe fn avx512(a: &[u8], b: &[u8]) -> u32 {
+    let mut total = _mm512_setzero_si512();
+    let chunks = a.len() / 64;
+    for chunk in 0..chunks {
+        let a = _mm512_loadu_si512(a.as_ptr().add(chunk * 64).cast());
+        let b = _mm512_loadu_si512(b.as_ptr().add(chunk * 64).cast());
+        total = _mm512_add_epi64(total, _mm512_popcnt_epi64(_mm512_xor_si512(a, b)));
+    }
+    let tail = chunks * 64;
+    _mm512_reduce_add_epi64(total) as u32 + scalar(&a[tail..], &b[tail..])
+}
+
+#[cfg(test)]
+#[test]
+fn test_kernels_agree() {
+    use crate::testing::XorShift;
+
+    let mut rng = XorShift::new(0xbb67_ae85_84ca_a73b);
+    for len in 0..300 {
+        let a: Vec<u8> = (0..len).map(|_| rng.next_u64() as u8).collect();
+        let b: Vec<u8> = (0..len).map(|_| rng.next_u64() as u8).collect();
+        let expected: u32 = a.iter().zip(&b).map(|(a, b)| (a ^ b).count_ones()).sum();
+        assert_eq!(scalar(&a, &b), expected);
+        assert_eq!(hamming(&a, &b), expected);
+        // Misaligned starts must not matter either.
+        if len > 1 {
+            assert_eq!(hamming(&a[1..], &b[1..]), scalar(&a[1..], &b[1..]));
+        }
+        #[cfg(target_arch = "x86_64")]
+        {
+            if is_x86_feature_detected!("avx2") {
+                assert_eq!(unsafe { avx2(&a, &b) }, expected);
+            }
+            #[cfg(feature = "avx512")]
+            if is_x86_feature_detected!("avx512f") && is_x86_feature_detected!("avx512vpopcntdq") {
+                assert_eq!(unsafe { avx512(&a, &b) }, expected);
+            }
+        }
+    }
+    let ones = [!0u64; 8];
+    assert_eq!(hamming_words(&ones, &[0; 8]), 512);
+}
+
+#[cfg(test)]
+#[test]
+fn test_hamming_bounded() {
+    let a = [0xffu8; 20];
+    let b = [0u8; 20];
+    assert_eq!(hamming_bounded(&a, &b, 161), BoundedDistance::Within(160));
+    // The first word alone reaches the bound, so the other two words are skipped.
+    assert_eq!(
+        hamming_bounded(&a, &b, 64),
+        BoundedDistance::Abandoned { skipped_words: 2 }
+    );
+    // The partial final word is the last one looked at.
+    assert_eq!(
+        hamming_bounded(&a, &b, 160),
+        BoundedDistance::Abandoned { skipped_words: 0 }
+    );
+    assert_eq!(
+        hamming_words_bounded(&[1, 3, 7, 15], &[0; 4], 4),
+        BoundedDistance::Abandoned { skipped_words: 1 }
+    );
+    assert_eq!(hamming_bounded(&[], &[], 1), BoundedDistance::Within(0));
+    assert_eq!(
+        hamming_bounded(&[], &[], 0),
+        BoundedDistance::Abandoned { skipped_words: 0 }
+    );
+    #[cfg(target_arch = "x86_64")]
+    {
+        if is_x86_feature_detected!("popcnt") {
+            for bound in 0..170 {
+                let c = &a[3..];
+                let d = &b[3..];
+                assert_eq!(
+                    unsafe { popcnt_bounded(c, d, bound) },
+                    scalar_bounded(c, d, bound)
+                );
+            }
+        }
+    }
+}
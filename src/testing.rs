@@ -0,0 +1,29 @@
+/// A small xorshift generator that keeps the tests deterministic without extra dependencies.
+pub(crate) struct XorShift(u64);
+
+impl XorShift {
+    /// Starts the generator from a nonzero `seed`.
+    pub(crate) fn new(seed: u64) -> Self {
+        assert_ne!(seed, 0, "xorshift gets stuck at zero");
+        XorShift(seed)
+    }
+
+    /// Gets the next 64 random bits.
+    pub(crate) fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    /// Gets a random number in `0..bound`.
+    pub(crate) fn below(&mut self, bound: u64) -> u64 {
+        self.next_u64() % bound
+    }
+}
+
+/// Inserts `value` into the sorted reference after every equal value, like the heaps order a new tie.
+pub(crate) fn insert_sorted<V: Ord>(reference: &mut Vec<V>, value: V) {
+    let position = reference.partition_point(|other| *other <= value);
+    reference.insert(position, value);
+}
@@ -0,0 +1,258 @@
+use crate::buckets::Buckets;
+use core::cmp::Ordering;
+use core::fmt;
+
+/// Chooses which of the items tied at a distance a heap removes first.
+///
+/// Every policy is deterministic, so the same pushes and pops always produce the same results. The policy applies
+/// to both ends of a heap: popping the best item takes the tied item the policy picks first at the best distance,
+/// and evicting the worst item takes the tied item the policy picks first at the worst distance.
+///
+/// ```
+/// use hamming_heap::{HammingHeap, TieBreak};
+/// let mut candidates = HammingHeap::new_distances(129);
+/// candidates.set_tie_break(TieBreak::Fifo);
+/// candidates.push(3, 'a');
+/// candidates.push(3, 'b');
+/// assert_eq!(candidates.pop(), Some((3, 'a')));
+/// ```
+#[derive(Default)]
+pub enum TieBreak<T> {
+    /// Removes the most recently pushed item first. This is the default and the only policy that never has to
+    /// look at the other items in a bucket.
+    #[default]
+    Lifo,
+    /// Removes the least recently pushed item first.
+    ///
+    /// The other items in the bucket are shifted down to keep their order, so this costs time linear in the number
+    /// of tied items.
+    Fifo,
+    /// Orders tied items with the function, so that the heap removes items in lexicographic `(distance, item)`
+    /// order. Popping the best item takes the smallest tied item and evicting the worst item takes the largest.
+    /// A full [`FixedHammingHeap`](crate::FixedHammingHeap) also keeps an item at its worst distance if the item
+    /// orders before one of the items tied there.
+    ///
+    /// Each bucket is kept sorted, so removing the best or worst tied item doesn't compare anything. Adding an item
+    /// to a distance that already holds `m` items finds its place with `O(log m)` comparisons and then shifts the
+    /// items ordered after it, so only contended distances pay for the ordering. Changing how items compare through
+    /// `iter_mut` or `bucket_mut` leaves the order of their bucket unspecified.
+    ///
+    /// Use [`TieBreak::ord`] to order by `T: Ord` or [`TieBreak::by_key`] to order by a secondary key.
+    Order(fn(&T, &T) -> Ordering),
+    /// Removes a uniformly random tied item.
+    ///
+    /// The value is the state of the random number generator and is advanced every time an item is picked, so
+    /// seeding it with the same value reproduces the same choices.
+    Random(u64),
+}
+
+impl<T> TieBreak<T>
+where
+    T: Ord,
+{
+    /// Orders tied items by `T: Ord`.
+    pub fn ord() -> Self {
+        TieBreak::Order(T::cmp)
+    }
+}
+
+impl<S, U> TieBreak<(S, U)>
+where
+    S: PartialOrd,
+{
+    /// Orders tied `(key, item)` pairs by a secondary key, like a float distance or a response strength.
+    ///
+    /// Keys that aren't comparable to themselves, like `NaN`, are ordered after every other key.
+    ///
+    /// ```
+    /// use hamming_heap::{FixedHammingHeap, TieBreak};
+    /// let mut candidates = FixedHammingHeap::new_distances(129);
+    /// candidates.set_capacity(2);
+    /// candidates.set_tie_break(TieBreak::by_key());
+    /// candidates.push(7, (0.5, 'a'));
+    /// candidates.push(7, (0.25, 'b'));
+    /// candidates.push(7, (0.125, 'c'));
+    /// candidates.push(9, (0.0, 'd'));
+    /// assert_eq!(
+    ///     candidates.into_sorted_vec(),
+    ///     [(7, (0.125, 'c')), (7, (0.25, 'b'))]
+    /// );
+    /// ```
+    pub fn by_key() -> Self {
+        TieBreak::Order(compare_keys::<S, U>)
+    }
+}
+
+/// Compares the keys of two pairs, ordering keys that aren't comparable to themselves last.
+#[allow(clippy::eq_op)]
+fn compare_keys<S: PartialOrd, U>(a: &(S, U), b: &(S, U)) -> Ordering {
+    a.0.partial_cmp(&b.0)
+        .unwrap_or_else(|| (b.0 == b.0).cmp(&(a.0 == a.0)))
+}
+
+/// The end of a heap that an item is being removed from.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub(crate) enum End {
+    Best,
+    Worst,
+}
+
+impl<T> TieBreak<T> {
+    /// Gets the index of the item in `items` to remove from the `end` of the heap.
+    ///
+    /// Returns `None` if `items` is empty.
+    #[inline]
+    pub(crate) fn select(&mut self, items: &[T], end: End) -> Option<usize> {
+        if items.is_empty() {
+            return None;
+        }
+        Some(match self {
+            TieBreak::Lifo => items.len() - 1,
+            TieBreak::Fifo => 0,
+            // Buckets are kept sorted from best to worst.
+            TieBreak::Order(_) => match end {
+                End::Best => 0,
+                End::Worst => items.len() - 1,
+            },
+            TieBreak::Random(state) => next_index(state, items.len()),
+        })
+    }
+}
+
+impl<T> TieBreak<T> {
+    /// Adds an item to `bucket`, keeping the bucket sorted if the items are ordered.
+    ///
+    /// Items that compare equal stay in the order they were added.
+    #[inline]
+    pub(crate) fn insert<B>(&self, buckets: &mut B, bucket: usize, item: T)
+    where
+        B: Buckets<T>,
+    {
+        match self {
+            TieBreak::Order(cmp) => {
+                let index = buckets
+                    .bucket(bucket)
+                    .partition_point(|other| cmp(other, &item) != Ordering::Greater);
+                buckets.insert(bucket, index, item);
+            }
+            _ => buckets.push(bucket, item),
+        }
+    }
+
+    /// Sorts every bucket if the items are ordered, which is needed after switching to an `Order` policy.
+    pub(crate) fn sort<B>(&self, buckets: &mut B)
+    where
+        B: Buckets<T>,
+    {
+        if let TieBreak::Order(cmp) = self {
+            for items in buckets.buckets_mut() {
+                items.sort_by(*cmp);
+            }
+        }
+    }
+
+    /// Removes the item that this policy picks from the `end` of the heap out of `bucket`.
+    ///
+    /// Returns `None` if the bucket is empty.
+    #[inline]
+    pub(crate) fn take<B>(&mut self, buckets: &mut B, bucket: usize, end: End) -> Option<T>
+    where
+        B: Buckets<T>,
+    {
+        match (&*self, end) {
+            (TieBreak::Lifo, _) | (TieBreak::Order(_), End::Worst) => return buckets.pop(bucket),
+            _ => {}
+        }
+        let index = self.select(buckets.bucket(bucket), end)?;
+        Some(buckets.remove(bucket, index))
+    }
+}
+
+impl<T> Clone for TieBreak<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for TieBreak<T> {}
+
+impl<T> fmt::Debug for TieBreak<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TieBreak::Lifo => write!(f, "Lifo"),
+            TieBreak::Fifo => write!(f, "Fifo"),
+            TieBreak::Order(_) => write!(f, "Order(..)"),
+            TieBreak::Random(state) => f.debug_tuple("Random").field(state).finish(),
+        }
+    }
+}
+
+/// Advances the SplitMix64 generator in `state` and maps its output onto `0..len`.
+fn next_index(state: &mut u64, len: usize) -> usize {
+    *state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    let mut z = *state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^= z >> 31;
+    // The bias of a multiply-shift reduction is at most `len / 2^64`.
+    ((u128::from(z) * len as u128) >> 64) as usize
+}
+
+#[cfg(test)]
+#[test]
+fn test_tie_break() {
+    use crate::{FixedHammingHeap, HammingHeap};
+
+    let pops = |policy| {
+        let mut candidates: HammingHeap<u32> = HammingHeap::new_distances(4);
+        candidates.set_tie_break(policy);
+        for &item in &[2, 0, 3, 1] {
+            candidates.push(1, item);
+        }
+        candidates.push(0, 9);
+        let mut popped = vec![];
+        while let Some((_, item)) = candidates.pop() {
+            popped.push(item);
+        }
+        popped
+    };
+    assert_eq!(pops(TieBreak::Lifo), [9, 1, 3, 0, 2]);
+    assert_eq!(pops(TieBreak::Fifo), [9, 2, 0, 3, 1]);
+    assert_eq!(pops(TieBreak::ord()), [9, 0, 1, 2, 3]);
+    assert_eq!(pops(TieBreak::Random(7)), pops(TieBreak::Random(7)));
+    let mut sorted = pops(TieBreak::Random(7));
+    sorted.sort_unstable();
+    assert_eq!(sorted, [0, 1, 2, 3, 9]);
+
+    let evicts = |policy| {
+        let mut candidates: FixedHammingHeap<u32> = FixedHammingHeap::new_distances(4);
+        candidates.set_tie_break(policy);
+        candidates.set_capacity(3);
+        for &item in &[2, 0, 3] {
+            candidates.push(2, item);
+        }
+        candidates.push(1, 8);
+        candidates.push(1, 9);
+        candidates.into_sorted_vec()
+    };
+    assert_eq!(evicts(TieBreak::Lifo), [(1, 9), (1, 8), (2, 2)]);
+    assert_eq!(evicts(TieBreak::Fifo), [(1, 8), (1, 9), (2, 3)]);
+    assert_eq!(evicts(TieBreak::ord()), [(1, 8), (1, 9), (2, 0)]);
+
+    // Switching to an order after pushing sorts the items already in the heap.
+    let mut candidates: HammingHeap<u32> = HammingHeap::new_distances(4);
+    for &item in &[2, 0, 3, 1] {
+        candidates.push(1, item);
+    }
+    candidates.set_tie_break(TieBreak::ord());
+    candidates.push(1, 1);
+    assert_eq!(candidates.bucket(1), [0, 1, 1, 2, 3]);
+
+    // Every tied item is equally likely to be picked.
+    let mut state = TieBreak::<()>::Random(1);
+    let mut counts = [0usize; 5];
+    for _ in 0..5000 {
+        counts[state.select(&[(); 5], End::Best).unwrap()] += 1;
+    }
+    assert!(counts.iter().all(|&count| count > 900 && count < 1100));
+}